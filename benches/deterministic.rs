@@ -0,0 +1,51 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use ndtm_rs::{Heuristic, Movement, RuleStore, SearchStrategy, Tape, Verdict, DTM, NDTM};
+
+/// Crosses out one `a` at a time, going back to the start of the word after each one. Takes a
+/// number of steps quadratic in the length of the word
+fn cross_out() -> RuleStore {
+    RuleStore::builder()
+        .rule(0, b'x', b'x', 0, Movement::Right)
+        .rule(0, b'a', b'x', 1, Movement::Right)
+        .rule(0, b'_', b'_', 3, Movement::Stay)
+        .rule(1, b'a', b'a', 1, Movement::Right)
+        .rule(1, b'x', b'x', 1, Movement::Right)
+        .rule(1, b'_', b'_', 2, Movement::Left)
+        .rule(2, b'a', b'a', 2, Movement::Left)
+        .rule(2, b'x', b'x', 2, Movement::Left)
+        .rule(2, b'_', b'_', 0, Movement::Right)
+        .accept(3)
+        .build()
+}
+
+fn long_run(c: &mut Criterion) {
+    let rules = cross_out();
+    let word = vec![b'a'; 200];
+    let max = 1_000_000;
+    let mut group = c.benchmark_group("cross out 200");
+    group.sample_size(20);
+    group.bench_function("NDTM", |b| {
+        b.iter(|| {
+            let tape: Tape<5> = Tape::create(b'_', &word);
+            let mut machine = NDTM::new(
+                tape,
+                &rules,
+                max,
+                Heuristic::State,
+                SearchStrategy::BestFirst,
+            );
+            assert_eq!(machine.run(), Verdict::Accept);
+        })
+    });
+    group.bench_function("DTM", |b| {
+        b.iter(|| {
+            let tape: Tape<5> = Tape::create(b'_', &word);
+            let mut machine = DTM::new(tape, &rules, max).unwrap();
+            assert_eq!(machine.run(), Verdict::Accept);
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, long_run);
+criterion_main!(benches);
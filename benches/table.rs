@@ -0,0 +1,75 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use ndtm_rs::{parse, Heuristic, RuleStore, SearchStrategy, Tape, Verdict, NDTM};
+
+/// Guesses a position in the word and accepts if the symbols at it and the next one are equal
+const DOUBLE: &str = "
+tr
+0 a a R 0
+0 b b R 0
+0 a a R 1
+0 b b R 2
+1 a a S 3
+2 b b S 3
+acc
+3
+max
+100000
+";
+
+/// Look up every pair of state and symbol, either cloning the rules or borrowing them
+fn lookups(c: &mut Criterion) {
+    let rules = parse(DOUBLE.as_bytes()).unwrap().rules;
+    let mut group = c.benchmark_group("lookup");
+    group.bench_function("get", |b| {
+        b.iter(|| {
+            let mut found = 0;
+            for state in 0..4 {
+                for symb in 0..=u8::MAX {
+                    if let ndtm_rs::rules::Output::Multi(trs) = rules.get(state, symb) {
+                        found += trs.len();
+                    }
+                }
+            }
+            black_box(found)
+        })
+    });
+    group.bench_function("transitions", |b| {
+        b.iter(|| {
+            let mut found = 0;
+            for state in 0..4 {
+                for symb in 0..=u8::MAX {
+                    let trs = rules.transitions(state, symb);
+                    if trs.len() > 1 {
+                        found += trs.len();
+                    }
+                }
+            }
+            black_box(found)
+        })
+    });
+    group.finish();
+}
+
+fn run(rules: &RuleStore, word: &[u8]) -> Verdict {
+    let tape: Tape<5> = Tape::create(b'_', word);
+    NDTM::new(
+        tape,
+        rules,
+        100_000,
+        Heuristic::State,
+        SearchStrategy::BestFirst,
+    )
+    .run()
+}
+
+/// A whole run which keeps splitting, the branches never match so the whole tree is explored
+fn branching_run(c: &mut Criterion) {
+    let rules = parse(DOUBLE.as_bytes()).unwrap().rules;
+    let word: Vec<u8> = b"ab".repeat(500);
+    c.bench_function("branching run", |b| {
+        b.iter(|| assert_eq!(run(&rules, &word), Verdict::Reject))
+    });
+}
+
+criterion_group!(benches, lookups, branching_run);
+criterion_main!(benches);
@@ -1,120 +1,470 @@
-mod machine;
-mod rules;
-mod tape;
-
-use crate::machine::{StepResult, NDTM};
-use crate::rules::RuleStore;
-use crate::tape::{Movement, Tape};
+use ndtm_rs::{
+    parse, parser, Description, Heuristic, RunStats, SearchStrategy, Step, SymbolTable, Tape,
+    TraceEvent, Verdict, DTM, NDTM,
+};
 use std::env;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::BufReader;
+use std::path::Path;
+use std::process;
 
 fn main() {
-    println!("Ciao");
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        println!("./bin input.txt")
+        println!(
+            "./bin input.txt [--heuristic=state|symbol] \
+             [--strategy=best-first|breadth-first|depth-first|iterative-deepening[:N]] \
+             [--witness] [--tape] [--dedup] [--detect-loops] [--reject=branch|abort] \
+             [--threads=N] [--stats] [--save=FILE]\n\
+             ./bin trace input.txt [--format=text|json] [--window=N] [options]\n\
+             ./bin dot|check input.txt"
+        );
+        return;
+    }
+    if let cmd @ ("dot" | "check") = args[1].as_str() {
+        let Some(filename) = args.get(2) else {
+            println!("./bin {} input.txt", cmd);
+            return;
+        };
+        let description = load_or_exit(filename);
+        if cmd == "dot" {
+            print!("{}", description.rules.to_dot(&description.names));
+        } else {
+            check(&description);
+        }
+        return;
     }
-    let filename = &args[1];
-    let file = File::open(filename).expect("Can't find");
-    let reader = BufReader::new(file);
+    // Tracing takes the same options as a plain run, plus the ones about the output
+    let tracing = args[1] == "trace";
+    let args = if tracing { &args[1..] } else { &args[..] };
+    let Some(filename) = args.get(1) else {
+        println!("./bin trace input.txt [--format=text|json] [--window=N] [options]");
+        return;
+    };
 
-    let mut rules = RuleStore::new();
-    let mut max_steps: usize = 0;
+    let mut heuristic = Heuristic::State;
+    let mut strategy = SearchStrategy::BestFirst;
+    let mut print_witness = false;
+    let mut print_tape = false;
+    let mut dedup = false;
+    let mut print_stats = false;
+    let mut detect_loops = false;
+    let mut abort_on_reject = false;
+    let mut threads: usize = 1;
+    let mut save: Option<&str> = None;
+    let mut json = false;
+    let mut window: usize = 3;
+    for arg in &args[2..] {
+        match arg.as_str() {
+            "--heuristic=state" => heuristic = Heuristic::State,
+            "--heuristic=symbol" => heuristic = Heuristic::Symbol,
+            "--strategy=best-first" => strategy = SearchStrategy::BestFirst,
+            "--strategy=breadth-first" => strategy = SearchStrategy::BreadthFirst,
+            "--strategy=depth-first" => strategy = SearchStrategy::DepthFirst,
+            "--strategy=iterative-deepening" => strategy = SearchStrategy::IterativeDeepening(16),
+            s if s.starts_with("--strategy=iterative-deepening:") => {
+                match s["--strategy=iterative-deepening:".len()..].parse() {
+                    Ok(n) => strategy = SearchStrategy::IterativeDeepening(n),
+                    Err(_) => println!("Invalid deepening step in {}", arg),
+                }
+            }
+            "--witness" => print_witness = true,
+            "--tape" => print_tape = true,
+            "--dedup" => dedup = true,
+            "--stats" => print_stats = true,
+            "--detect-loops" => detect_loops = true,
+            "--reject=branch" => abort_on_reject = false,
+            "--reject=abort" => abort_on_reject = true,
+            s if s.starts_with("--threads=") => match s["--threads=".len()..].parse() {
+                Ok(n) => threads = n,
+                Err(_) => println!("Invalid number of threads in {}", arg),
+            },
+            s if s.starts_with("--save=") => save = Some(&s["--save=".len()..]),
+            "--format=text" if tracing => json = false,
+            "--format=json" if tracing => json = true,
+            s if tracing && s.starts_with("--window=") => match s["--window=".len()..].parse() {
+                Ok(n) => window = n,
+                Err(_) => println!("Invalid window in {}", arg),
+            },
+            _ => println!("Unknown option {}", arg),
+        }
+    }
+    let description = load_or_exit(filename);
+    if let Some(out) = save {
+        if let Err(e) = store(&description, out) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    let Description {
+        rules,
+        max_steps,
+        blank,
+        inputs,
+        names,
+    } = description;
 
-    let mut stage = 0;
+    // The words in the description take precedence over the standard input
+    let words: Box<dyn Iterator<Item = Vec<u8>>> = if inputs.is_empty() {
+        Box::new(
+            std::io::stdin()
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|line| match parser::parse_word(&line, &names) {
+                    Ok(word) => Some(word),
+                    Err(e) => {
+                        eprintln!("<stdin>:{}", e);
+                        None
+                    }
+                }),
+        )
+    } else {
+        Box::new(inputs.into_iter())
+    };
 
-    for line in reader.lines() {
-        let line = line.unwrap();
-        if line.contains("tr") {
-            stage = 1;
-            continue;
+    // Without branches the fast runner gives the same verdicts, unless something only the
+    // nondeterministic one tracks was asked for
+    let fast = rules.tapes() == 1
+        && rules.is_deterministic()
+        && !print_witness
+        && !dedup
+        && !print_stats
+        && !detect_loops
+        && !tracing;
+    for (input, word) in words.enumerate() {
+        if !(tracing && json) {
+            println!("{}", names.word(&word));
         }
-        if line.contains("acc") {
-            stage = 2;
+        let tape: Tape<5> = Tape::create(blank, &word);
+        if fast {
+            let mut machine = DTM::new(tape, &rules, max_steps).unwrap();
+            let verdict = machine.run();
+            print_verdict(verdict, rules.is_reject(machine.state()));
+            if verdict == Verdict::Accept && print_tape {
+                show_tape(machine.tape(), &names);
+            }
             continue;
         }
-        if line.contains("max") {
-            stage = 3;
+        let mut machine = NDTM::builder(&rules)
+            .max_steps(max_steps)
+            .heuristic(heuristic)
+            .strategy(strategy)
+            .deduplicate(dedup)
+            .detect_loops(detect_loops)
+            .abort_on_reject(abort_on_reject)
+            .record_witness(print_witness)
+            .build(tape);
+        if tracing {
+            machine.trace(Some(window));
+            let verdict = trace(&mut machine, &names, json.then_some(input));
+            if json {
+                print_json_verdict(input, verdict);
+            } else {
+                print_verdict(verdict, machine.some_rejected());
+            }
             continue;
         }
-        if line.contains("run") {
-            stage = 4;
-            continue;
+        let verdict = run(&mut machine, threads);
+        if dedup {
+            println!("Pruned {} duplicate branches", machine.pruned());
         }
-
-        match stage {
-            1 => {
-                let v: Vec<&str> = line.split(' ').collect();
-                dbg!(&v);
-                rules.add_rule(
-                    v[0].parse().unwrap(),
-                    v[1].as_bytes()[0],
-                    v[2].as_bytes()[0],
-                    v[4].parse().unwrap(),
-                    match v[3] {
-                        "R" => Movement::Right,
-                        "L" => Movement::Left,
-                        "S" => Movement::Stay,
-                        _ => {
-                            panic!()
-                        }
-                    },
-                );
-            }
-            2 => {
-                rules.add_final(line.parse().unwrap());
-                dbg!(&line);
-            }
-            3 => {
-                max_steps = line.parse().unwrap();
-                dbg!(max_steps);
+        print_verdict(verdict, machine.some_rejected());
+        if print_stats {
+            show_stats(&machine.stats());
+        }
+        if verdict == Verdict::Accept {
+            if print_witness {
+                for step in machine.witness().unwrap_or_default() {
+                    print_step(&step, &names);
+                }
             }
-            _ => {
-                break;
+            if print_tape {
+                for tape in machine.accepted_tapes().unwrap_or_default() {
+                    show_tape(tape, &names);
+                }
             }
         }
     }
+}
 
-    //rules.compute_dist();
-    let mut input = String::new();
-    while true {
-        input.clear();
-        match std::io::stdin().read_line(&mut input) {
-            Ok(s) => {
-                println!("{}", s);
-                if s == 0 {
-                    break;
-                }
-            }
-            Err(_) => {
-                break;
-            }
+/// Rejections caused by a reject state are told apart from the ones where the branches just
+/// ran out of transitions
+fn print_verdict(verdict: Verdict, rejected: bool) {
+    match verdict {
+        Verdict::Accept => println!("Success"),
+        Verdict::Reject if rejected => println!("Rejected"),
+        Verdict::Reject => println!("Failure"),
+        Verdict::Undecided => println!("Undecided"),
+    }
+}
+
+fn show_stats(stats: &RunStats) {
+    println!(
+        "Steps: {} ({} deterministic, {} splits)",
+        stats.steps, stats.det_steps, stats.splits
+    );
+    println!(
+        "Branches: {} created, {} failed, {} cut by the step limit",
+        stats.created, stats.failed, stats.undecided
+    );
+    println!(
+        "Peak frontier: {} branches, peak live cells: {}",
+        stats.peak_frontier, stats.peak_cells
+    );
+    println!("Time: {:?}", stats.elapsed);
+}
+
+/// Print the non blank part of the tape with a caret under the head
+fn show_tape<const W: usize>(tape: &Tape<W>, names: &SymbolTable) {
+    let (symbols, head) = tape.contents();
+    println!("{}", names.word(&symbols));
+    if head >= 0 {
+        let head = (head as usize).min(symbols.len());
+        let before = names.word(&symbols[..head]).chars().count();
+        // Skip the separator between the names, if any
+        let sep = if head > 0 && !names.is_empty() { 1 } else { 0 };
+        println!("{}^", " ".repeat(before + sep));
+    }
+}
+
+fn load_or_exit(filename: &str) -> Description {
+    match load(filename) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Print where the machine is nondeterministic and the warnings about the rules of the
+/// description, exiting with an error if there are any warnings
+fn check(description: &Description) {
+    let names = &description.names;
+    let determinism = description.rules.determinism();
+    if determinism.deterministic {
+        println!("Deterministic");
+    } else {
+        println!(
+            "Nondeterministic, {} choices with up to {} branches",
+            determinism.choices.len(),
+            determinism.max_branching()
+        );
+        for choice in &determinism.choices {
+            println!(
+                "  {} {}: {} branches",
+                names.state_name(choice.state),
+                choice
+                    .symbols
+                    .iter()
+                    .map(|s| names.symbol_name(*s))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                choice.fan_out
+            );
+        }
+    }
+    let warnings = description.rules.analyze();
+    if warnings.is_empty() {
+        println!("No problems found");
+        return;
+    }
+    for warning in &warnings {
+        println!("warning: {}", warning.message(names));
+    }
+    process::exit(1);
+}
+
+/// Read a description, JSON and TOML files are recognised by their extension
+fn load(filename: &str) -> Result<Description, String> {
+    match Path::new(filename).extension().and_then(|e| e.to_str()) {
+        Some(ext @ ("json" | "toml")) => {
+            let text =
+                std::fs::read_to_string(filename).map_err(|e| format!("{}: {}", filename, e))?;
+            load_structured(&text, ext).map_err(|e| format!("{}:{}", filename, e))
         }
-        input.pop();
-        let slice = input.as_bytes();
-        let mut machine: NDTM<5> = NDTM::new(Tape::create(b'_', slice), &rules, max_steps);
-        let mut res = machine.fastforward(None);
-        dbg!(&res);
-        match res.pop() {
-            None => {
-                panic!()
+        _ => {
+            let file = File::open(filename).map_err(|e| format!("{}: {}", filename, e))?;
+            parse(BufReader::new(file)).map_err(|e| format!("{}:{}", filename, e))
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn load_structured(text: &str, ext: &str) -> Result<Description, ndtm_rs::ParseError> {
+    match ext {
+        "json" => ndtm_rs::parser::from_json(text),
+        _ => ndtm_rs::parser::from_toml(text),
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn load_structured(_: &str, ext: &str) -> Result<Description, String> {
+    Err(format!(" {} descriptions require the serde feature", ext))
+}
+
+/// Write the description to a JSON or TOML file, chosen by its extension
+#[cfg(feature = "serde")]
+fn store(description: &Description, filename: &str) -> Result<(), String> {
+    let text = match Path::new(filename).extension().and_then(|e| e.to_str()) {
+        Some("json") => ndtm_rs::parser::to_json(description),
+        Some("toml") => ndtm_rs::parser::to_toml(description),
+        _ => {
+            return Err(format!(
+                "{}: descriptions can only be saved as json or toml",
+                filename
+            ))
+        }
+    };
+    std::fs::write(filename, text).map_err(|e| format!("{}: {}", filename, e))
+}
+
+#[cfg(not(feature = "serde"))]
+fn store(_: &Description, filename: &str) -> Result<(), String> {
+    Err(format!(
+        "{}: saving descriptions requires the serde feature",
+        filename
+    ))
+}
+
+/// Print a step in the same format used to describe the transitions
+fn print_step(step: &Step, names: &SymbolTable) {
+    println!("{}", describe_step(step, names));
+}
+
+fn describe_step(step: &Step, names: &SymbolTable) -> String {
+    let tr = step.transition;
+    let reads = std::iter::once(step.read).chain(step.others.iter().map(|o| o.read));
+    let writes = std::iter::once(tr.symb).chain(step.others.iter().map(|o| o.write));
+    let dirs = std::iter::once(tr.dir).chain(step.others.iter().map(|o| o.dir));
+    let mut text = names.state_name(step.state);
+    for symb in reads.chain(writes) {
+        text.push(' ');
+        text.push_str(&names.symbol_name(symb));
+    }
+    for dir in dirs {
+        text.push(' ');
+        text.push_str(dir.as_str());
+    }
+    text.push(' ');
+    text.push_str(&names.state_name(tr.state));
+    if let Some(c) = step.choice {
+        text.push_str(&format!(" (choice {})", c));
+    }
+    text
+}
+
+/// Run the computation one step at a time, printing the transitions applied as text or, when
+/// the index of the input is given, as JSON objects, one per line
+fn trace<const W: usize>(
+    machine: &mut NDTM<W>,
+    names: &SymbolTable,
+    json: Option<usize>,
+) -> Verdict {
+    while machine.next().is_some() {
+        for event in machine.take_trace() {
+            match json {
+                Some(input) => print_json_event(input, &event, names),
+                None => print_event(&event, names),
             }
-            Some(x) => match x {
-                StepResult::Success { .. } => {
-                    println!("Success")
-                }
-                StepResult::FailAll => {
-                    if machine.some_undecided {
-                        println!("Undecided")
-                    } else {
-                        println!("Failure")
-                    }
-                }
-                _ => {
-                    panic!()
-                }
-            },
         }
     }
+    // The computation already ended, this only gives its verdict
+    machine.run_until(0).verdict
+}
+
+/// Print a transition applied by a machine, with the tape around the head and the symbol
+/// under it between brackets
+fn print_event(event: &TraceEvent, names: &SymbolTable) {
+    let parent = match event.parent {
+        Some(p) => format!(" (from {})", p),
+        None => String::new(),
+    };
+    let mid = event.window.len() / 2;
+    println!(
+        "  machine {}{} at {}: {}  {}[{}]{}",
+        event.machine,
+        parent,
+        event.head,
+        describe_step(&event.step, names),
+        names.word(&event.window[..mid]),
+        names.symbol_name(event.window[mid]),
+        names.word(&event.window[mid + 1..]),
+    );
+}
+
+#[cfg(feature = "serde")]
+fn print_json_event(input: usize, event: &TraceEvent, names: &SymbolTable) {
+    let step = &event.step;
+    let tr = step.transition;
+    let mut value = serde_json::json!({
+        "input": input,
+        "machine": event.machine,
+        "parent": event.parent,
+        "state": names.state_name(step.state),
+        "head": event.head,
+        "read": names.symbol_name(step.read),
+        "write": names.symbol_name(tr.symb),
+        "dir": tr.dir.as_str(),
+        "next": names.state_name(tr.state),
+        "choice": step.choice,
+        "window": event.window.iter().map(|s| names.symbol_name(*s)).collect::<Vec<_>>(),
+    });
+    if !step.others.is_empty() {
+        value["others"] = step
+            .others
+            .iter()
+            .map(|o| {
+                serde_json::json!({
+                    "read": names.symbol_name(o.read),
+                    "write": names.symbol_name(o.write),
+                    "dir": o.dir.as_str(),
+                })
+            })
+            .collect();
+    }
+    println!("{}", value);
+}
+
+#[cfg(feature = "serde")]
+fn print_json_verdict(input: usize, verdict: Verdict) {
+    let verdict = match verdict {
+        Verdict::Accept => "accept",
+        Verdict::Reject => "reject",
+        Verdict::Undecided => "undecided",
+    };
+    println!(
+        "{}",
+        serde_json::json!({ "input": input, "verdict": verdict })
+    );
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_json_event(_: usize, _: &TraceEvent, _: &SymbolTable) {
+    eprintln!("JSON traces require the serde feature");
+    process::exit(1);
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_json_verdict(_: usize, _: Verdict) {
+    eprintln!("JSON traces require the serde feature");
+    process::exit(1);
+}
+
+#[cfg(feature = "parallel")]
+fn run<const W: usize>(machine: &mut NDTM<W>, threads: usize) -> Verdict {
+    if threads > 1 {
+        machine.par_run(threads)
+    } else {
+        machine.run()
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+fn run<const W: usize>(machine: &mut NDTM<W>, threads: usize) -> Verdict {
+    if threads > 1 {
+        println!("Multiple threads require the parallel feature, running on one");
+    }
+    machine.run()
 }
@@ -0,0 +1,41 @@
+/*!
+A simulator for nondeterministic Turing machines.
+
+A machine is described by a `RuleStore`, built directly, with `RuleStore::builder` or by parsing
+its textual description with `parse`. An `NDTM` then runs the machine on a `Tape`, exploring the
+branches of the computation until one of them accepts, they all stop, or they are all cut by the
+step limit.
+
+```
+use ndtm_rs::{Movement, RuleStore, Tape, Verdict, NDTM, Heuristic, SearchStrategy};
+
+// Accepts the words made only of `a`
+let rules = RuleStore::builder()
+    .rule(0, b'a', b'a', 0, Movement::Right)
+    .rule(0, b'_', b'_', 1, Movement::Stay)
+    .accept(1)
+    .build();
+let tape: Tape<4> = Tape::create(b'_', b"aaa");
+let mut machine = NDTM::new(tape, &rules, 100, Heuristic::State, SearchStrategy::BestFirst);
+assert_eq!(machine.run(), Verdict::Accept);
+```
+*/
+
+pub mod machine;
+pub mod names;
+pub mod parser;
+pub mod rules;
+mod shared;
+pub mod tape;
+
+pub use crate::machine::{
+    Branch, NDTMBuilder, Observer, RunStats, SearchStrategy, Step, StepResult, Summary, TraceEvent,
+    Verdict, DTM, NDTM,
+};
+pub use crate::names::SymbolTable;
+pub use crate::parser::{parse, Description, ParseError};
+pub use crate::rules::{
+    Choice, Determinism, Heuristic, MultiTransition, Pattern, RuleStore, RuleStoreBuilder,
+    Transition, Warning, Write,
+};
+pub use crate::tape::{Movement, Tape};
@@ -0,0 +1,60 @@
+/*!
+This module detects branches stuck in a loop.
+
+A branch which reaches the same configuration twice will keep repeating the same steps forever,
+any other choice it could make along the loop is being explored by the branches created when it
+split, so it can be killed without changing the outcome of the computation.
+
+Comparing each configuration against all the previous ones would be too expensive, instead the
+configuration is saved at exponentially spaced checkpoints (Brent's algorithm): a loop is found
+at most twice its length plus the number of steps before it was entered.
+*/
+
+use crate::tape::Tape;
+
+/// A configuration of a machine: its state and, for each tape, the non empty region of the tape
+/// and the position of the head relative to it
+type Config = (usize, Vec<(Vec<u8>, isize)>);
+
+#[derive(Clone)]
+pub struct CycleDetector {
+    checkpoint: Option<Config>,
+    /// Steps taken since the last checkpoint
+    steps: usize,
+    /// Steps to take before moving the checkpoint
+    power: usize,
+}
+
+impl Default for CycleDetector {
+    fn default() -> Self {
+        CycleDetector {
+            checkpoint: None,
+            steps: 0,
+            power: 1,
+        }
+    }
+}
+
+impl CycleDetector {
+    /// Feed the configuration reached after a step, returns true if it was proven to be part of
+    /// a loop
+    pub fn check<'t, const W: usize>(
+        &mut self,
+        state: usize,
+        tapes: impl Iterator<Item = &'t Tape<W>>,
+    ) -> bool {
+        let contents: Vec<(Vec<u8>, isize)> = tapes.map(|t| t.contents()).collect();
+        if let Some((c_state, c_contents)) = &self.checkpoint {
+            if *c_state == state && *c_contents == contents {
+                return true;
+            }
+        }
+        self.steps += 1;
+        if self.steps >= self.power || self.checkpoint.is_none() {
+            self.checkpoint = Some((state, contents));
+            self.power *= 2;
+            self.steps = 0;
+        }
+        false
+    }
+}
@@ -0,0 +1,76 @@
+use crate::machine::Verdict;
+use crate::rules::RuleStore;
+use crate::tape::Tape;
+
+/// Runner for rule sets without nondeterministic choices.
+///
+/// A single machine is run in a loop, without the queue of branches and the bookkeeping
+/// needed to split them. The verdicts are the same an `NDTM` would give
+#[allow(clippy::upper_case_acronyms)]
+pub struct DTM<'a, const W: usize> {
+    rules: &'a RuleStore,
+    tape: Tape<W>,
+    state: usize,
+    steps: usize,
+    max_step: usize,
+}
+
+impl<'a, const W: usize> DTM<'a, W> {
+    /// Returns None if the rules aren't deterministic, see `RuleStore::is_deterministic`, or
+    /// if they are for many tapes
+    pub fn new(tape: Tape<W>, rules: &'a RuleStore, max: usize) -> Option<Self> {
+        if rules.tapes() > 1 || !rules.is_deterministic() {
+            return None;
+        }
+        Some(DTM {
+            rules,
+            tape,
+            state: 0,
+            steps: 0,
+            max_step: max,
+        })
+    }
+
+    /// Run the machine until it stops or reaches the step limit
+    pub fn run(&mut self) -> Verdict {
+        // Like the NDTM, give up as soon as the machine is in a state from which no final
+        // state is reachable
+        if self.rules.distance(self.state).is_none() {
+            return Verdict::Reject;
+        }
+        while self.steps < self.max_step {
+            let tr = match self.rules.transitions(self.state, self.tape.read()) {
+                [tr] => *tr,
+                [] => return Verdict::Reject,
+                _ => panic!("Checked to be deterministic on creation"),
+            };
+            self.tape.write(tr.symb);
+            self.tape.shift(tr.dir);
+            self.steps += 1;
+            if self.rules.is_final(tr.state) {
+                self.state = tr.state;
+                return Verdict::Accept;
+            }
+            if tr.state != self.state {
+                self.state = tr.state;
+                if self.rules.distance(self.state).is_none() {
+                    return Verdict::Reject;
+                }
+            }
+        }
+        Verdict::Undecided
+    }
+
+    /// The number of transitions taken
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn state(&self) -> usize {
+        self.state
+    }
+
+    pub fn tape(&self) -> &Tape<W> {
+        &self.tape
+    }
+}
@@ -0,0 +1,92 @@
+/*!
+This module keeps track of the transitions applied by each branch of the computation.
+
+Like the cells of a tape, the history is shared between a machine and all the machines created
+by splitting it: every branch only owns the steps it took after the split and links to the
+history of its parent for everything that came before.
+*/
+
+use crate::rules::Transition;
+use crate::shared::Ptr;
+use crate::tape::Movement;
+
+/// A single transition applied by a machine
+#[derive(Clone, Debug)]
+pub struct Step {
+    /// The state the machine was in before the transition
+    pub state: usize,
+    /// The symbol under the head before the transition
+    pub read: u8,
+    /// If the transition was one of many available, its index among the rules for
+    /// (state, read) in the order they were added
+    pub choice: Option<usize>,
+    /// The new state and what was done on the first tape
+    pub transition: Transition,
+    /// What was done on the other tapes of a machine with many, empty otherwise
+    pub others: Vec<TapeStep>,
+}
+
+/// What a transition did on one of the tapes
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TapeStep {
+    /// The symbol under the head before the transition
+    pub read: u8,
+    pub write: u8,
+    pub dir: Movement,
+}
+
+struct Node {
+    step: Step,
+    prev: Option<Ptr<Node>>,
+}
+
+/// The sequence of steps taken by a machine since the start of the computation
+#[derive(Clone, Default)]
+pub struct History {
+    last: Option<Ptr<Node>>,
+}
+
+impl History {
+    pub fn push(&mut self, step: Step) {
+        let prev = self.last.take();
+        self.last = Some(Ptr::new(Node { step, prev }));
+    }
+
+    /// Forget the steps taken so far, keeping only `step`
+    pub fn replace(&mut self, step: Step) {
+        *self = History {
+            last: Some(Ptr::new(Node { step, prev: None })),
+        };
+    }
+
+    /// The step taken most recently, if any
+    pub fn last(&self) -> Option<&Step> {
+        self.last.as_ref().map(|node| &node.step)
+    }
+
+    /// Collect the steps from the first to the last
+    pub fn to_vec(&self) -> Vec<Step> {
+        let mut v = Vec::new();
+        let mut curr = &self.last;
+        while let Some(node) = curr {
+            v.push(node.step.clone());
+            curr = &node.prev;
+        }
+        v.reverse();
+        v
+    }
+}
+
+impl Drop for History {
+    /// Drop the nodes no other branch is referencing one at a time, the default recursive drop
+    /// would overflow the stack on long computations
+    fn drop(&mut self) {
+        let mut curr = self.last.take();
+        while let Some(rc) = curr {
+            match Ptr::try_unwrap(rc) {
+                Ok(mut node) => curr = node.prev.take(),
+                Err(_) => break,
+            }
+        }
+    }
+}
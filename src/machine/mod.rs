@@ -1,235 +1,1003 @@
-use crate::rules::{Output, RuleStore, Transition};
+use crate::machine::cycle::CycleDetector;
+use crate::machine::history::History;
+use crate::machine::store::MachineStore;
+use crate::machine::trace::{Snapshot, Tracer};
+use crate::rules::{Heuristic, MultiTransition, RuleStore, Transition};
+use crate::shared::Counter;
 use crate::tape::Tape;
 use std::cmp::Ordering;
-use std::collections::BinaryHeap;
-use std::mem::transmute;
+use std::time::Instant;
 
-pub struct NDTM<'a, const W: usize> {
+pub use crate::machine::dtm::DTM;
+pub use crate::machine::history::{Step, TapeStep};
+pub use crate::machine::observer::{Branch, Observer};
+pub use crate::machine::stats::RunStats;
+pub use crate::machine::store::SearchStrategy;
+pub use crate::machine::trace::TraceEvent;
+
+mod cycle;
+mod dtm;
+mod history;
+mod observer;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod stats;
+mod store;
+mod trace;
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct NDTM<'a, const W: usize, O: Observer<W> = ()> {
     rules: &'a RuleStore,
-    machines: MachineStore<W>,
+    machines: MachineStore<'a, W>,
     /// The last index used to number a machine
     last_idx: usize,
-    pub some_undecided: bool,
+    some_undecided: bool,
     max_step: usize,
+    heuristic: Heuristic,
+    detect_loops: bool,
+    /// Whether a branch entering a reject state stops the whole computation
+    abort_on_reject: bool,
+    some_rejected: bool,
+    /// Whether the branches keep all the transitions they applied, see `witness`
+    record_witness: bool,
+    /// Records the transitions applied, if tracing
+    tracer: Option<Tracer>,
+    /// The machine which reached a final state, if any
+    accepted: Option<TM<'a, W>>,
+    /// The verdict, once the computation ended
+    verdict: Option<Verdict>,
+    stats: RunStats,
+    /// How many cells the tapes the computation started from and the ones split from them hold
+    cells: Vec<Counter>,
+    /// When the computation was last started or resumed, None if it's not running
+    clock: Option<Instant>,
+    observer: O,
 }
 
 impl<'a, const W: usize> NDTM<'a, W> {
-    pub fn new(tape: Tape<W>, rules: &'a RuleStore, max: usize) -> Self {
-        let mut store = MachineStore::new();
-        let first = TM::new(tape, 0, Option::None, 0, 0, rules.distance(0));
-        store.push(first);
+    /// Configure a machine running `rules`, without a step limit and with the defaults of the
+    /// other options
+    pub fn builder(rules: &'a RuleStore) -> NDTMBuilder<'a, W> {
+        NDTMBuilder {
+            rules,
+            max: usize::MAX,
+            heuristic: Heuristic::State,
+            strategy: SearchStrategy::BestFirst,
+            dedup: false,
+            detect_loops: false,
+            abort_on_reject: false,
+            record_witness: false,
+        }
+    }
+
+    /// Run the machine with the input on `tape`. If the rules are for many tapes the others
+    /// start out empty, filled with the same empty symbol
+    pub fn new(
+        tape: Tape<W>,
+        rules: &'a RuleStore,
+        max: usize,
+        heuristic: Heuristic,
+        strategy: SearchStrategy,
+    ) -> Self {
+        let others = (1..rules.tapes())
+            .map(|_| Tape::create(tape.empty(), &[]))
+            .collect();
+        Self::start(tape, others, rules, max, heuristic, strategy)
+    }
+
+    /// Run a machine with many tapes, starting from the given ones.
+    ///
+    /// # Panics
+    ///
+    /// If the number of tapes isn't the one the rules are for
+    pub fn with_tapes(
+        tapes: Vec<Tape<W>>,
+        rules: &'a RuleStore,
+        max: usize,
+        heuristic: Heuristic,
+        strategy: SearchStrategy,
+    ) -> Self {
+        assert_eq!(
+            tapes.len(),
+            rules.tapes(),
+            "The rules are for a different number of tapes"
+        );
+        let mut tapes = tapes.into_iter();
+        let tape = tapes.next().unwrap();
+        Self::start(tape, tapes.collect(), rules, max, heuristic, strategy)
+    }
+
+    fn start(
+        tape: Tape<W>,
+        others: Vec<Tape<W>>,
+        rules: &'a RuleStore,
+        max: usize,
+        heuristic: Heuristic,
+        strategy: SearchStrategy,
+    ) -> Self {
+        let cells = std::iter::once(&tape)
+            .chain(others.iter())
+            .map(Tape::cell_counter)
+            .collect();
+        let mut store = MachineStore::new(strategy);
+        // If neither a final nor a reject state is reachable from the initial state the store is
+        // left empty and the computation fails on the first step. A machine which can only
+        // reject is kept in case it has to abort the computation, otherwise it fails on its
+        // first step
+        let dist = rules
+            .estimate(heuristic, 0, tape.read())
+            .or_else(|| rules.reject_distance(0));
+        if let Some(dist) = dist {
+            let mut tm = TM::new(tape, 0, None, 0, 0, dist, History::default());
+            tm.others = others;
+            store.push(tm);
+        }
         NDTM {
             rules,
             last_idx: 0,
             machines: store,
             some_undecided: false,
             max_step: max,
+            heuristic,
+            detect_loops: false,
+            abort_on_reject: false,
+            some_rejected: false,
+            record_witness: false,
+            tracer: None,
+            accepted: None,
+            verdict: None,
+            stats: RunStats::default(),
+            cells,
+            clock: None,
+            observer: (),
         }
     }
+}
+
+impl<'a, const W: usize, O: Observer<W>> NDTM<'a, W, O> {
+    /// Report the events of the computation to `observer` from now on, replacing the current one
+    pub fn with_observer<P: Observer<W>>(self, observer: P) -> NDTM<'a, W, P> {
+        NDTM {
+            rules: self.rules,
+            machines: self.machines,
+            last_idx: self.last_idx,
+            some_undecided: self.some_undecided,
+            max_step: self.max_step,
+            heuristic: self.heuristic,
+            detect_loops: self.detect_loops,
+            abort_on_reject: self.abort_on_reject,
+            some_rejected: self.some_rejected,
+            record_witness: self.record_witness,
+            tracer: self.tracer,
+            accepted: self.accepted,
+            verdict: self.verdict,
+            stats: self.stats,
+            cells: self.cells,
+            clock: self.clock,
+            observer,
+        }
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
 
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Advance the next branch of the computation.
+    ///
+    /// Once the computation ended nothing is done anymore, the last result is given again
     pub fn step(&mut self) -> StepResult {
-        let machine = self.machines.pop();
-        return if let Some(mut machine) = machine {
-            let id = machine.idx;
-            if machine.depth >= self.max_step {
-                self.some_undecided |= true;
-                return StepResult::Undecided { machine: id };
+        match self.verdict {
+            Some(Verdict::Accept) => {
+                let machine = self.accepted.as_ref().map_or(0, |m| m.idx);
+                return StepResult::Success { machine };
             }
-            let step_res = machine.step(self.rules);
-            match step_res {
-                TMStepRes::Success => {
-                    self.machines.push(machine);
-                    StepResult::DetStep { machine: id }
+            Some(_) => return StepResult::FailAll,
+            None => {}
+        }
+        self.clock.get_or_insert_with(Instant::now);
+        match self.machines.pop() {
+            Some(machine) => {
+                let (advance, event) = self.stepper().advance(machine);
+                let r = self.record(advance, event);
+                if let StepResult::Success { .. } = r {
+                    self.end(Verdict::Accept);
                 }
-                TMStepRes::Failure => StepResult::BranchFail { machine: id },
-                TMStepRes::Split(mut trs) => {
-                    let (state, depth, mut split) = machine.split(trs.len());
-                    let mut ret: Vec<usize> = Vec::with_capacity(trs.len());
-
-                    let dist = self.rules.distance(state);
-                    for tape in split {
-                        self.last_idx += 1;
-                        if let Some(trans) = trs.pop() {
-                            let tm = TM::new(tape, state, Some(trans), self.last_idx, depth, dist);
-                            ret.push(self.last_idx);
-                            self.machines.push(tm)
-                        } else {
-                            panic!("Not enough rules for the split, impossible")
-                        }
+                r
+            }
+            None => {
+                self.observer.exhausted();
+                self.end(self.verdict_on_fail());
+                StepResult::FailAll
+            }
+        }
+    }
+
+    /// Keep the outcome of the computation, which won't be advanced anymore
+    fn end(&mut self, verdict: Verdict) {
+        self.stop_clock();
+        self.verdict = Some(verdict);
+    }
+
+    /// Count the time since the computation was started or resumed, until it's resumed again
+    fn stop_clock(&mut self) {
+        if let Some(start) = self.clock.take() {
+            self.stats.elapsed += start.elapsed();
+        }
+    }
+
+    /// How much work the computation took so far
+    pub fn stats(&self) -> RunStats {
+        let mut stats = self.stats;
+        if let Some(start) = self.clock {
+            stats.elapsed += start.elapsed();
+        }
+        stats
+    }
+
+    /// What's needed to advance the machines of the computation
+    fn stepper(&self) -> Stepper<'a> {
+        Stepper {
+            rules: self.rules,
+            max_step: self.max_step,
+            heuristic: self.heuristic,
+            detect_loops: self.detect_loops,
+            abort_on_reject: self.abort_on_reject,
+            record_witness: self.record_witness,
+            radius: self.tracer.as_ref().map(Tracer::radius),
+        }
+    }
+
+    /// Number the machines created by `advance` and queue them
+    fn record(&mut self, advance: Advance<'a, W>, event: Option<TraceEvent>) -> StepResult {
+        if let (Some(tracer), Some(event)) = (&mut self.tracer, event) {
+            tracer.push(event);
+        }
+        self.stats.steps += 1;
+        let r = match advance {
+            Advance::Undecided(machine) => {
+                self.observer.undecided(Branch::new(&machine));
+                self.some_undecided |= true;
+                self.stats.undecided += 1;
+                StepResult::Undecided {
+                    machine: machine.idx,
+                }
+            }
+            Advance::Step(machine) => {
+                let id = machine.idx;
+                // The first step of a branch created by a split applies the transition it was
+                // given, chosen among many
+                let det = machine.history.last().is_some_and(|s| s.choice.is_none());
+                self.observer.stepped(Branch::new(&machine));
+                match self.machines.push(machine) {
+                    None => {
+                        self.stats.det_steps += usize::from(det);
+                        StepResult::DetStep { machine: id }
+                    }
+                    Some(duplicate) => {
+                        self.observer.pruned(Branch::new(&duplicate));
+                        StepResult::Pruned { machine: id }
                     }
-                    if !trs.is_empty() {
-                        panic!("There should have been enough tapes")
+                }
+            }
+            Advance::Fail(machine) => {
+                self.observer.failed(Branch::new(&machine));
+                self.stats.failed += 1;
+                StepResult::BranchFail {
+                    machine: machine.idx,
+                }
+            }
+            Advance::Loop(machine) => {
+                self.observer.looped(Branch::new(&machine));
+                self.stats.failed += 1;
+                StepResult::Loop {
+                    machine: machine.idx,
+                }
+            }
+            Advance::Rejected(machine) => {
+                let id = machine.idx;
+                self.observer.rejected(Branch::new(&machine));
+                self.stats.failed += 1;
+                self.some_rejected = true;
+                if self.abort_on_reject {
+                    while self.machines.pop().is_some() {}
+                }
+                StepResult::Rejected { machine: id }
+            }
+            Advance::Split(id, children) => {
+                self.stats.splits += 1;
+                self.stats.created += children.len();
+                let mut ret: Vec<usize> = Vec::with_capacity(children.len());
+                for mut tm in children {
+                    self.last_idx += 1;
+                    tm.idx = self.last_idx;
+                    self.observer.created(id, Branch::new(&tm));
+                    match self.machines.push(tm) {
+                        None => ret.push(self.last_idx),
+                        Some(duplicate) => self.observer.pruned(Branch::new(&duplicate)),
                     }
+                }
+                if let Some(tracer) = &mut self.tracer {
+                    tracer.split(id, &ret);
+                }
+                if ret.is_empty() {
+                    StepResult::Pruned { machine: id }
+                } else {
                     StepResult::Split {
                         source: id,
                         new: ret,
                     }
                 }
-                TMStepRes::Recognized => StepResult::Success { machine: id },
             }
-        } else {
-            StepResult::FailAll
+            Advance::Recognized(machine) => {
+                let id = machine.idx;
+                self.observer.accepted(Branch::new(&machine));
+                self.accepted = Some(machine);
+                StepResult::Success { machine: id }
+            }
         };
+        self.stats.peak_frontier = self.stats.peak_frontier.max(self.machines.len());
+        let cells = self.cells.iter().map(Counter::get).sum();
+        self.stats.peak_cells = self.stats.peak_cells.max(cells);
+        r
     }
 
-    pub fn fastforward(&mut self, steps: Option<usize>) -> Vec<StepResult> {
-        let mut vec = Vec::new();
-        let count = 0;
+    /// Start or stop discarding branches which reach a configuration (state, tape contents and
+    /// head position) already reached by another branch
+    pub fn deduplicate(&mut self, enable: bool) {
+        self.machines.deduplicate(enable)
+    }
+
+    /// Start or stop killing branches which are proven to be looping forever
+    pub fn detect_loops(&mut self, enable: bool) {
+        self.detect_loops = enable;
+    }
+
+    /// Choose what happens when a branch enters a reject state: if enabled the whole computation
+    /// stops and rejects the input, otherwise only that branch stops as if it had no transition
+    /// available.
+    ///
+    /// When enabled the branches which can reach a reject state but no final state are kept
+    /// running, otherwise they are pruned like the others which can't accept
+    pub fn abort_on_reject(&mut self, enable: bool) {
+        self.abort_on_reject = enable;
+    }
+
+    /// Start or stop keeping every transition applied by the branches, so that `witness` and
+    /// `Branch::steps` can return them. The memory used grows with the number of steps, without
+    /// it only the last transition of each branch is kept
+    pub fn record_witness(&mut self, enable: bool) {
+        self.record_witness = enable;
+    }
+
+    /// Start recording the transitions applied by the machines, keeping `radius` symbols on each
+    /// side of the head, or stop if None. Tracing slows the computation down considerably
+    pub fn trace(&mut self, radius: Option<usize>) {
+        self.tracer = radius.map(Tracer::new);
+    }
+
+    /// The transitions recorded since the last call, in the order they were applied
+    pub fn take_trace(&mut self) -> Vec<TraceEvent> {
+        self.tracer.as_mut().map(Tracer::take).unwrap_or_default()
+    }
+
+    /// How many branches were discarded as duplicates
+    pub fn pruned(&self) -> usize {
+        self.machines.pruned
+    }
+
+    /// The computation of the branch which reached a final state, from the initial
+    /// configuration to the accepting one.
+    ///
+    /// Returns None if no branch was accepted (yet) or if the transitions weren't recorded, see
+    /// `record_witness`
+    pub fn witness(&self) -> Option<Vec<Step>> {
+        self.accepted
+            .as_ref()
+            .filter(|_| self.record_witness)
+            .map(|m| m.history.to_vec())
+    }
+
+    /// The tape of the branch which reached a final state, None if no branch was accepted (yet).
+    ///
+    /// For machines with many tapes this is the first one
+    pub fn accepted_tape(&self) -> Option<&Tape<W>> {
+        self.accepted.as_ref().map(|m| &m.tape)
+    }
+
+    /// All the tapes of the branch which reached a final state, None if no branch was accepted
+    /// (yet)
+    pub fn accepted_tapes(&self) -> Option<Vec<&Tape<W>>> {
+        self.accepted
+            .as_ref()
+            .map(|m| std::iter::once(&m.tape).chain(m.others.iter()).collect())
+    }
+
+    /// True if some branch was stopped for reaching the maximum number of steps
+    pub fn some_undecided(&self) -> bool {
+        self.some_undecided
+    }
+
+    /// True if some branch entered a reject state, as opposed to all the branches stopping
+    /// for the lack of transitions.
+    ///
+    /// Branches are stopped as soon as they can't reach a final state, so unless the computation
+    /// aborts on reject states they are only entered from states from which acceptance was
+    /// still possible
+    pub fn some_rejected(&self) -> bool {
+        self.some_rejected
+    }
+
+    /// Run the computation to the end and return its outcome, right away if it already ended
+    pub fn run(&mut self) -> Verdict {
         loop {
-            if let Some(m) = steps {
-                if count >= m {
-                    break;
-                }
-            }
-            let r = self.step();
-            match r {
-                r @ StepResult::Success { .. } => {
-                    vec.push(r);
-                    break;
-                }
-                r @ StepResult::FailAll => {
-                    vec.push(r);
-                    break;
-                }
-                r @ _ => {
-                    vec.push(r);
-                }
+            if let Some(verdict) = self.verdict {
+                return verdict;
             }
+            self.step();
+        }
+    }
+
+    /// Run the computation for at most `budget` more steps, stopping early if it ends.
+    ///
+    /// The computation can be resumed by calling this again, or iterating on the machine, if it
+    /// didn't end within the budget
+    pub fn run_until(&mut self, budget: usize) -> Summary {
+        let steps = self.by_ref().take(budget).count();
+        Summary {
+            verdict: self.verdict.unwrap_or(Verdict::Undecided),
+            finished: self.verdict.is_some(),
+            steps,
+            branches: self.last_idx + 1,
+        }
+    }
+
+    /// The outcome when no branch is left: if some branch was cut by the step limit it might
+    /// have accepted with more steps, unless the computation was aborted by a reject state
+    fn verdict_on_fail(&self) -> Verdict {
+        if self.some_undecided && !(self.abort_on_reject && self.some_rejected) {
+            Verdict::Undecided
+        } else {
+            Verdict::Reject
         }
-        return vec;
+    }
+
+    /// Run the computation for at most `steps` steps, or to the end if None, and return the
+    /// result of every step. The last one is `Success` or `FailAll` if the computation ended,
+    /// nothing is returned if it had already ended.
+    ///
+    /// The results are all kept in memory, long computations are better run as an iterator
+    pub fn fastforward(&mut self, steps: Option<usize>) -> Vec<StepResult> {
+        self.by_ref().take(steps.unwrap_or(usize::MAX)).collect()
     }
 }
 
+/// Chainable configuration of an `NDTM`
+pub struct NDTMBuilder<'a, const W: usize> {
+    rules: &'a RuleStore,
+    max: usize,
+    heuristic: Heuristic,
+    strategy: SearchStrategy,
+    dedup: bool,
+    detect_loops: bool,
+    abort_on_reject: bool,
+    record_witness: bool,
+}
+
+impl<'a, const W: usize> NDTMBuilder<'a, W> {
+    /// Stop the branches after `max` transitions, see `Verdict::Undecided`
+    pub fn max_steps(mut self, max: usize) -> Self {
+        self.max = max;
+        self
+    }
+
+    pub fn heuristic(mut self, heuristic: Heuristic) -> Self {
+        self.heuristic = heuristic;
+        self
+    }
+
+    pub fn strategy(mut self, strategy: SearchStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// See `NDTM::deduplicate`
+    pub fn deduplicate(mut self, enable: bool) -> Self {
+        self.dedup = enable;
+        self
+    }
+
+    /// See `NDTM::detect_loops`
+    pub fn detect_loops(mut self, enable: bool) -> Self {
+        self.detect_loops = enable;
+        self
+    }
+
+    /// See `NDTM::abort_on_reject`
+    pub fn abort_on_reject(mut self, enable: bool) -> Self {
+        self.abort_on_reject = enable;
+        self
+    }
+
+    /// See `NDTM::record_witness`
+    pub fn record_witness(mut self, enable: bool) -> Self {
+        self.record_witness = enable;
+        self
+    }
+
+    /// Start the machine with the input on `tape`, see `NDTM::new`
+    pub fn build(self, tape: Tape<W>) -> NDTM<'a, W> {
+        let machine = NDTM::new(tape, self.rules, self.max, self.heuristic, self.strategy);
+        self.configure(machine)
+    }
+
+    /// Start a machine with many tapes, see `NDTM::with_tapes`
+    pub fn build_with_tapes(self, tapes: Vec<Tape<W>>) -> NDTM<'a, W> {
+        let machine = NDTM::with_tapes(tapes, self.rules, self.max, self.heuristic, self.strategy);
+        self.configure(machine)
+    }
+
+    fn configure(&self, mut machine: NDTM<'a, W>) -> NDTM<'a, W> {
+        machine.deduplicate(self.dedup);
+        machine.detect_loops(self.detect_loops);
+        machine.abort_on_reject(self.abort_on_reject);
+        machine.record_witness(self.record_witness);
+        machine
+    }
+}
+
+/// Steps the computation, ending after the first `Success` or `FailAll`
+impl<'a, const W: usize, O: Observer<W>> Iterator for NDTM<'a, W, O> {
+    type Item = StepResult;
+
+    fn next(&mut self) -> Option<StepResult> {
+        if self.verdict.is_some() {
+            return None;
+        }
+        Some(self.step())
+    }
+}
+
+/// How a computation went, as returned by `NDTM::run_until`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Summary {
+    /// The outcome of the computation, Undecided if it didn't end within the budget
+    pub verdict: Verdict,
+    /// Whether the computation ended, otherwise it can be resumed
+    pub finished: bool,
+    /// How many steps were taken by this call
+    pub steps: usize,
+    /// How many branches were created since the start, the initial one included
+    pub branches: usize,
+}
+
+/// The outcome of a whole computation
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// A branch reached a final state
+    Accept,
+    /// Every branch stopped without reaching a final state
+    Reject,
+    /// No branch reached a final state, but some were stopped by the step limit
+    Undecided,
+}
+
 #[derive(Debug)]
 pub enum StepResult {
-    Undecided { machine: usize },
-    DetStep { machine: usize },
-    Split { source: usize, new: Vec<usize> },
-    BranchFail { machine: usize },
-    Success { machine: usize },
+    Undecided {
+        machine: usize,
+    },
+    DetStep {
+        machine: usize,
+    },
+    Split {
+        source: usize,
+        new: Vec<usize>,
+    },
+    BranchFail {
+        machine: usize,
+    },
+    /// The machine reached a configuration it had already been in, so it would loop forever
+    Loop {
+        machine: usize,
+    },
+    /// The machine entered a reject state. If the computation is aborted on rejection no
+    /// machine is left
+    Rejected {
+        machine: usize,
+    },
+    /// The machine reached a configuration already reached by another one and was discarded, or
+    /// went past the limit of iterative deepening
+    Pruned {
+        machine: usize,
+    },
+    Success {
+        machine: usize,
+    },
     FailAll,
 }
 
-/// The struct used to queue machines for executions
-struct MachineStore<const W: usize> {
-    heap: BinaryHeap<TM<W>>,
+/// The outcome of `Stepper::advance`. The machines which stop are kept until the outcome is
+/// recorded, to be shown to the observer
+enum Advance<'r, const W: usize> {
+    Undecided(TM<'r, W>),
+    Step(TM<'r, W>),
+    Fail(TM<'r, W>),
+    Loop(TM<'r, W>),
+    Rejected(TM<'r, W>),
+    /// The source machine and its children, which haven't been numbered yet
+    Split(usize, Vec<TM<'r, W>>),
+    Recognized(TM<'r, W>),
 }
 
-impl<const W: usize> MachineStore<W> {
-    fn new() -> Self {
-        MachineStore {
-            heap: BinaryHeap::new(),
+/// What's needed to advance a machine besides the machine itself. It doesn't borrow the `NDTM`,
+/// so that machines can be advanced while the results of others are recorded
+#[derive(Copy, Clone)]
+struct Stepper<'a> {
+    rules: &'a RuleStore,
+    max_step: usize,
+    heuristic: Heuristic,
+    detect_loops: bool,
+    abort_on_reject: bool,
+    record_witness: bool,
+    /// How many symbols around the head are traced, None if not tracing
+    radius: Option<usize>,
+}
+
+impl<'a> Stepper<'a> {
+    /// Execute a step of the given machine, the result must then be given to `NDTM::record`
+    fn advance<const W: usize>(
+        &self,
+        mut machine: TM<'a, W>,
+    ) -> (Advance<'a, W>, Option<TraceEvent>) {
+        if machine.depth >= self.max_step {
+            return (Advance::Undecided(machine), None);
         }
+        let snapshot = self.radius.map(|r| Snapshot::take(&machine, r));
+        let step_res = machine.step(self);
+        let event = snapshot.and_then(|s| s.finish(&machine));
+        (self.outcome(machine, step_res), event)
     }
-    /// Add a machine to the queue
-    fn push(&mut self, machine: TM<W>) {
-        self.heap.push(machine)
+
+    /// Estimate the distance from the end of the computation of a machine in `state` reading
+    /// `symb`, None if it can't end it. When entering a reject state aborts the computation,
+    /// the machines which can only reject are kept and estimated by the closest reject state
+    fn estimate(&self, state: usize, symb: u8) -> Option<usize> {
+        self.rules
+            .estimate(self.heuristic, state, symb)
+            .or_else(|| {
+                if self.abort_on_reject {
+                    self.rules.reject_distance(state)
+                } else {
+                    None
+                }
+            })
     }
 
-    fn pop(&mut self) -> Option<TM<W>> {
-        self.heap.pop()
+    /// Whether a machine moving to `state` may still end the computation
+    fn keep(&self, state: usize) -> bool {
+        self.rules.distance(state).is_some()
+            || self.rules.is_reject(state)
+            || (self.abort_on_reject && self.rules.reject_distance(state).is_some())
+    }
+
+    /// What happens to a machine after its step
+    fn outcome<const W: usize>(
+        &self,
+        machine: TM<'a, W>,
+        step_res: TMStepRes<'a>,
+    ) -> Advance<'a, W> {
+        let id = machine.idx;
+        match step_res {
+            TMStepRes::Success => Advance::Step(machine),
+            TMStepRes::Failure => Advance::Fail(machine),
+            TMStepRes::Loop => Advance::Loop(machine),
+            TMStepRes::Rejected => Advance::Rejected(machine),
+            TMStepRes::Split(trs) => {
+                // Branches moving to a state from which no final state is reachable are
+                // pruned before paying for the tape split, unless it's a reject state since
+                // entering it has to be reported, or one leading to a reject state when that
+                // aborts the computation
+                let keep = |state| self.keep(state);
+                let mut trs: Vec<(usize, Instr)> = match trs {
+                    Alternatives::Single(trs) => trs
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, t)| keep(t.state))
+                        .map(|(i, t)| (i, Instr::Single(*t)))
+                        .collect(),
+                    Alternatives::Multi(trs) => trs
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, t)| keep(t.state))
+                        .map(|(i, t)| (i, Instr::Multi(t)))
+                        .collect(),
+                };
+                if trs.is_empty() {
+                    return Advance::Fail(machine);
+                }
+                let split = machine.split(trs.len());
+                let mut children = Vec::with_capacity(trs.len());
+
+                for (tape, others) in split.copies {
+                    if let Some((choice, trans)) = trs.pop() {
+                        // The priority of a child is given by the state it's moving to, the
+                        // symbol it will read there isn't known until the move is done. Branches
+                        // going to a reject state come first, they stop right away
+                        let dist = self.rules.distance(trans.state()).unwrap_or(0);
+                        let mut tm = TM::new(
+                            tape,
+                            split.state,
+                            Some(trans),
+                            0,
+                            split.depth,
+                            dist,
+                            split.history.clone(),
+                        );
+                        tm.choice = Some(choice);
+                        tm.cycle = split.cycle.clone();
+                        tm.others = others;
+                        children.push(tm);
+                    } else {
+                        panic!("Not enough rules for the split, impossible")
+                    }
+                }
+                if !trs.is_empty() {
+                    panic!("There should have been enough tapes")
+                }
+                Advance::Split(id, children)
+            }
+            TMStepRes::Recognized => Advance::Recognized(machine),
+        }
     }
 }
 
-struct TM<const W: usize> {
+struct TM<'r, const W: usize> {
     depth: usize,
     idx: usize,
     tape: Tape<W>,
+    /// The tapes after the first of a machine with many, empty otherwise
+    others: Vec<Tape<W>>,
     state: usize,
     distance: usize,
-    instr_cache: Option<Transition>,
+    instr_cache: Option<Instr<'r>>,
+    /// The index of `instr_cache` among the alternatives it was chosen from
+    choice: Option<usize>,
+    history: History,
+    cycle: CycleDetector,
 }
 
-impl<const W: usize> TM<W> {
+impl<'r, const W: usize> TM<'r, W> {
     fn new(
         tape: Tape<W>,
         state: usize,
-        rule: Option<Transition>,
+        rule: Option<Instr<'r>>,
         id: usize,
         depth: usize,
         dist: usize,
+        history: History,
     ) -> Self {
         TM {
             idx: id,
             tape,
+            others: Vec::new(),
             state,
             instr_cache: rule,
+            choice: None,
             depth,
             distance: dist,
+            history,
+            cycle: CycleDetector::default(),
         }
     }
 
-    fn step(&mut self, rules: &RuleStore) -> TMStepRes {
-        if let Some(trs) = self.instr_cache.take() {
+    fn step(&mut self, stepper: &Stepper<'r>) -> TMStepRes<'r> {
+        let rules = stepper.rules;
+        if let Some(instr) = self.instr_cache.take() {
             self.depth += 1;
+            let (trs, others) = match instr {
+                Instr::Single(trs) => (trs, Vec::new()),
+                Instr::Multi(trs) => {
+                    let others = self
+                        .others
+                        .iter_mut()
+                        .zip(trs.symbs[1..].iter().zip(trs.dirs[1..].iter()))
+                        .map(|(tape, (&write, &dir))| {
+                            let read = tape.write(write);
+                            tape.shift(dir);
+                            TapeStep { read, write, dir }
+                        })
+                        .collect();
+                    let first = Transition {
+                        state: trs.state,
+                        symb: trs.symbs[0],
+                        dir: trs.dirs[0],
+                    };
+                    (first, others)
+                }
+            };
+            let read = self.tape.write(trs.symb);
+            let step = Step {
+                state: self.state,
+                read,
+                choice: self.choice.take(),
+                transition: trs,
+                others,
+            };
+            if stepper.record_witness {
+                self.history.push(step);
+            } else {
+                self.history.replace(step);
+            }
             self.state = trs.state;
-            self.distance = rules.distance(self.state);
-            self.tape.write(trs.symb);
             self.tape.shift(trs.dir);
 
             return if rules.is_final(self.state) {
                 TMStepRes::Recognized
-            } else {
+            } else if rules.is_reject(self.state) {
+                TMStepRes::Rejected
+            } else if stepper.detect_loops
+                && self
+                    .cycle
+                    .check(self.state, std::iter::once(&self.tape).chain(&self.others))
+            {
+                TMStepRes::Loop
+            } else if let Some(dist) = stepper.estimate(self.state, self.tape.read()) {
+                self.distance = dist;
                 TMStepRes::Success
+            } else {
+                TMStepRes::Failure
             };
         }
-        let rule = rules.get(self.state, self.tape.read());
-        match rule {
-            Output::None => TMStepRes::Failure,
-            Output::Simple(trs) => {
-                self.instr_cache = Some(trs);
-                return self.step(rules);
+        if !self.others.is_empty() {
+            return self.step_multi(stepper);
+        }
+        match rules.transitions(self.state, self.tape.read()) {
+            [] => TMStepRes::Failure,
+            [trs] => {
+                self.instr_cache = Some(Instr::Single(*trs));
+                self.step(stepper)
             }
-            Output::Multi(trs) => {
-                return TMStepRes::Split(trs);
+            trs => TMStepRes::Split(Alternatives::Single(trs)),
+        }
+    }
+
+    /// Same as the end of `step`, looking up the rules with the symbols under all the heads
+    fn step_multi(&mut self, stepper: &Stepper<'r>) -> TMStepRes<'r> {
+        let reads: Vec<u8> = std::iter::once(&self.tape)
+            .chain(&self.others)
+            .map(|t| t.read())
+            .collect();
+        match stepper.rules.multi_transitions(self.state, &reads) {
+            [] => TMStepRes::Failure,
+            [trs] => {
+                self.instr_cache = Some(Instr::Multi(trs));
+                self.step(stepper)
             }
+            trs => TMStepRes::Split(Alternatives::Multi(trs)),
         }
     }
 
-    /// Split the machine, destroying it and creating a given number of tape
-    /// copies. The first element of the return value is the source state of the machine,
-    /// the second is the vector of tapes
-    fn split(self, num: usize) -> (usize, usize, Vec<Tape<W>>) {
+    /// Split the machine in two identical ones. It can't be about to apply a transition
+    fn fork(self) -> (Self, Self) {
+        let (idx, distance) = (self.idx, self.distance);
+        let SplitTM {
+            state,
+            depth,
+            history,
+            cycle,
+            copies,
+        } = self.split(2);
+        let mut copies = copies.into_iter().map(|(tape, others)| {
+            let mut tm = TM::new(tape, state, None, idx, depth, distance, history.clone());
+            tm.cycle = cycle.clone();
+            tm.others = others;
+            tm
+        });
+        (copies.next().unwrap(), copies.next().unwrap())
+    }
+
+    /// Split the machine, destroying it and creating a given number of copies of its tapes
+    fn split(self, num: usize) -> SplitTM<W> {
         let TM {
             tape,
+            others,
             state,
             instr_cache,
             depth,
+            history,
+            cycle,
             ..
         } = self;
         if instr_cache.is_some() {
             panic!("Cache should always be empty if splitting the machine");
         }
-        (state, depth, tape.split(num))
+        // All the tapes split together, the n-th copy of each goes to the n-th machine
+        let mut others: Vec<_> = others
+            .into_iter()
+            .map(|t| t.split(num).into_iter())
+            .collect();
+        let copies = tape
+            .split(num)
+            .into_iter()
+            .map(|t| (t, others.iter_mut().map(|o| o.next().unwrap()).collect()))
+            .collect();
+        SplitTM {
+            state,
+            depth,
+            history,
+            cycle,
+            copies,
+        }
+    }
+}
+
+/// What's left of a machine after it was split, to create its children from
+struct SplitTM<const W: usize> {
+    state: usize,
+    depth: usize,
+    history: History,
+    cycle: CycleDetector,
+    /// A copy of the tapes for each child, made of the first tape and the others
+    copies: Vec<(Tape<W>, Vec<Tape<W>>)>,
+}
+
+/// The instruction a machine is about to apply
+#[derive(Clone, Hash)]
+enum Instr<'r> {
+    Single(Transition),
+    /// Borrowed from the rules, so that applying it doesn't copy the symbols and movements
+    Multi(&'r MultiTransition),
+}
+
+impl Instr<'_> {
+    fn state(&self) -> usize {
+        match self {
+            Instr::Single(trs) => trs.state,
+            Instr::Multi(trs) => trs.state,
+        }
     }
 }
 
-enum TMStepRes {
+/// The rules available to a machine which has to split
+enum Alternatives<'r> {
+    Single(&'r [Transition]),
+    Multi(&'r [MultiTransition]),
+}
+
+enum TMStepRes<'r> {
     /// Successfully transitioned
     Success,
     /// No transition available
     Failure,
+    /// The machine is in a configuration it already was in
+    Loop,
+    /// In a reject state
+    Rejected,
     /// There is a move available but it's non deterministic
-    Split(Vec<Transition>),
+    Split(Alternatives<'r>),
     /// In a final state
     Recognized,
 }
 
-impl<const W: usize> Eq for TM<W> {}
+impl<const W: usize> Eq for TM<'_, W> {}
 
-impl<const W: usize> PartialEq<Self> for TM<W> {
+impl<const W: usize> PartialEq<Self> for TM<'_, W> {
     fn eq(&self, other: &Self) -> bool {
-        self == other
+        self.cmp(other) == Ordering::Equal
     }
 }
 
-impl<const W: usize> PartialOrd<Self> for TM<W> {
+impl<const W: usize> PartialOrd<Self> for TM<'_, W> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl<const W: usize> Ord for TM<W> {
+impl<const W: usize> Ord for TM<'_, W> {
     fn cmp(&self, other: &Self) -> Ordering {
         let min_s = self.distance + self.depth;
         let min_o = other.distance + other.depth;
@@ -0,0 +1,124 @@
+/*!
+This module lets code outside of the simulator react to what happens to the branches of the
+computation, without going through the results of every step.
+
+An `NDTM` tells its observer about every branch which steps, is created, stops or accepts, right
+when it happens. The default observer is `()`, which ignores everything: its methods are empty
+and inlined away, so a machine without an observer runs as fast as before.
+*/
+
+use crate::machine::{Step, TM};
+use crate::tape::Tape;
+
+/// Receives the events of a computation, all the methods do nothing by default.
+///
+/// Every method is given a view of the branch the event is about, taken right after its step.
+/// A branch that stops is not reported as having stepped, even if it applied a transition
+#[allow(unused_variables)]
+pub trait Observer<const W: usize> {
+    /// The branch applied a transition and keeps running
+    fn stepped(&mut self, branch: Branch<'_, W>) {}
+    /// The branch was created by splitting `source`, it will apply its transition on its first
+    /// step
+    fn created(&mut self, source: usize, branch: Branch<'_, W>) {}
+    /// The branch stopped for the lack of transitions
+    fn failed(&mut self, branch: Branch<'_, W>) {}
+    /// The branch stopped in a configuration it had already been in
+    fn looped(&mut self, branch: Branch<'_, W>) {}
+    /// The branch entered a reject state
+    fn rejected(&mut self, branch: Branch<'_, W>) {}
+    /// The branch was discarded for reaching a configuration another one already reached, or
+    /// for going past the limit of iterative deepening
+    fn pruned(&mut self, branch: Branch<'_, W>) {}
+    /// The branch was cut by the step limit
+    fn undecided(&mut self, branch: Branch<'_, W>) {}
+    /// The branch reached a final state, ending the computation
+    fn accepted(&mut self, branch: Branch<'_, W>) {}
+    /// No branch is left and none was accepted
+    fn exhausted(&mut self) {}
+}
+
+impl<const W: usize> Observer<W> for () {}
+
+/// Lets the caller keep the observer and look at it while the computation runs
+impl<const W: usize, T: Observer<W> + ?Sized> Observer<W> for &mut T {
+    fn stepped(&mut self, branch: Branch<'_, W>) {
+        (**self).stepped(branch)
+    }
+
+    fn created(&mut self, source: usize, branch: Branch<'_, W>) {
+        (**self).created(source, branch)
+    }
+
+    fn failed(&mut self, branch: Branch<'_, W>) {
+        (**self).failed(branch)
+    }
+
+    fn looped(&mut self, branch: Branch<'_, W>) {
+        (**self).looped(branch)
+    }
+
+    fn rejected(&mut self, branch: Branch<'_, W>) {
+        (**self).rejected(branch)
+    }
+
+    fn pruned(&mut self, branch: Branch<'_, W>) {
+        (**self).pruned(branch)
+    }
+
+    fn undecided(&mut self, branch: Branch<'_, W>) {
+        (**self).undecided(branch)
+    }
+
+    fn accepted(&mut self, branch: Branch<'_, W>) {
+        (**self).accepted(branch)
+    }
+
+    fn exhausted(&mut self) {
+        (**self).exhausted()
+    }
+}
+
+/// A read only view of a branch of the computation
+#[derive(Copy, Clone)]
+pub struct Branch<'m, const W: usize> {
+    machine: &'m TM<'m, W>,
+}
+
+impl<'m, const W: usize> Branch<'m, W> {
+    pub(super) fn new(machine: &'m TM<'m, W>) -> Self {
+        Branch { machine }
+    }
+
+    /// The number of the branch, the same found in the results of `NDTM::step`
+    pub fn id(&self) -> usize {
+        self.machine.idx
+    }
+
+    pub fn state(&self) -> usize {
+        self.machine.state
+    }
+
+    /// How many transitions the branch applied since the start of the computation
+    pub fn depth(&self) -> usize {
+        self.machine.depth
+    }
+
+    /// The first tape of the branch
+    pub fn tape(&self) -> &'m Tape<W> {
+        &self.machine.tape
+    }
+
+    /// All the tapes of the branch, in order
+    pub fn tapes(&self) -> Vec<&'m Tape<W>> {
+        std::iter::once(&self.machine.tape)
+            .chain(self.machine.others.iter())
+            .collect()
+    }
+
+    /// The transitions applied by the branch, from the start of the computation. Only the last
+    /// one unless the machine is recording them, see `NDTM::record_witness`
+    pub fn steps(&self) -> Vec<Step> {
+        self.machine.history.to_vec()
+    }
+}
@@ -0,0 +1,146 @@
+/*!
+This module provides a runner which advances many branches of the computation at the same time.
+
+Machines are taken from the frontier in batches and split among the worker threads, the results
+are then recorded in the same order the machines were taken, so that the numbering of the
+branches and the outcome don't depend on the number of threads nor on their scheduling.
+
+The workers are started once for the whole computation and wait for the machines on a channel.
+Batches too small to be worth sending to them are advanced by the calling thread.
+*/
+
+use crate::machine::{Advance, Observer, StepResult, Stepper, TraceEvent, Verdict, NDTM, TM};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Instant;
+
+/// How many machines are taken from the frontier at every round. It doesn't depend on the number
+/// of threads so that the same machines are advanced together no matter how many are used
+const BATCH: usize = 64;
+
+/// The fewest machines handed to a thread at once, fewer are cheaper to advance than to send
+const MIN_PART: usize = 16;
+
+type Advanced<'r, const W: usize> = Vec<(Advance<'r, W>, Option<TraceEvent>)>;
+
+/// A thread advancing the machines it's sent, one part of a batch at a time
+struct Worker<'r, const W: usize> {
+    machines: Sender<Vec<TM<'r, W>>>,
+    advanced: Receiver<Advanced<'r, W>>,
+}
+
+impl<'r, const W: usize> Worker<'r, W> {
+    fn start<'s>(scope: &'s thread::Scope<'s, '_>, stepper: Stepper<'r>) -> Self
+    where
+        'r: 's,
+    {
+        let (machines, to_advance) = mpsc::channel::<Vec<TM<'r, W>>>();
+        let (done, advanced) = mpsc::channel();
+        // The worker stops when the sender of the machines is dropped
+        scope.spawn(move || {
+            for part in to_advance {
+                let part: Advanced<'r, W> = part.into_iter().map(|m| stepper.advance(m)).collect();
+                if done.send(part).is_err() {
+                    break;
+                }
+            }
+        });
+        Worker { machines, advanced }
+    }
+}
+
+/// The observer is only called from the thread running the computation, the workers are only
+/// given the machines and the rules
+impl<'a, const W: usize, O: Observer<W>> NDTM<'a, W, O> {
+    /// Same as `run` but using `threads` threads to advance the machines, the calling one
+    /// included. See `par_fastforward` for when the verdict may differ
+    pub fn par_run(&mut self, threads: usize) -> Verdict {
+        self.par_fastforward(threads);
+        self.run()
+    }
+
+    /// Same as `fastforward(None)` but using `threads` threads to advance the machines, the
+    /// calling one included.
+    ///
+    /// The machines of a batch are advanced together, so the order of the results may differ
+    /// from the sequential runner, but the final verdict is the same. Unless entering a reject
+    /// state aborts the computation: then the first branch to accept or reject decides, and it
+    /// may not be the same one. The verdict doesn't depend on the number of threads either way
+    pub fn par_fastforward(&mut self, threads: usize) -> Vec<StepResult> {
+        let threads = threads.max(1);
+        if self.verdict.is_some() {
+            return Vec::new();
+        }
+        let stepper = self.stepper();
+        thread::scope(|s| {
+            let workers: Vec<Worker<W>> = (1..threads).map(|_| Worker::start(s, stepper)).collect();
+            self.par_loop(stepper, &workers)
+        })
+    }
+
+    fn par_loop(&mut self, stepper: Stepper<'a>, workers: &[Worker<'a, W>]) -> Vec<StepResult> {
+        let mut vec = Vec::new();
+        self.clock.get_or_insert_with(Instant::now);
+        loop {
+            let mut batch = Vec::with_capacity(BATCH);
+            while batch.len() < BATCH {
+                match self.machines.pop() {
+                    Some(machine) => batch.push(machine),
+                    None => break,
+                }
+            }
+            if batch.is_empty() {
+                self.observer.exhausted();
+                self.end(self.verdict_on_fail());
+                vec.push(StepResult::FailAll);
+                return vec;
+            }
+
+            // The calling thread advances the first part and the workers the others
+            let parts = (batch.len() / MIN_PART).clamp(1, workers.len() + 1);
+            let chunk = batch.len().div_ceil(parts);
+            let mut rest = batch.split_off(chunk);
+            let mut busy = Vec::with_capacity(parts - 1);
+            while !rest.is_empty() {
+                let part = rest.drain(..chunk.min(rest.len())).collect();
+                let worker = &workers[busy.len()];
+                worker
+                    .machines
+                    .send(part)
+                    .expect("A worker thread panicked");
+                busy.push(worker);
+            }
+            let mut advanced: Advanced<'a, W> =
+                batch.into_iter().map(|m| stepper.advance(m)).collect();
+            for worker in busy {
+                advanced.extend(worker.advanced.recv().expect("A worker thread panicked"));
+            }
+
+            let mut waiting = advanced.len();
+            for (advance, event) in advanced {
+                let r = self.record(advance, event);
+                // The machines of the batch not recorded yet are still waiting, as they would be
+                // in the frontier of the sequential runner
+                waiting -= 1;
+                let frontier = self.machines.len() + waiting;
+                self.stats.peak_frontier = self.stats.peak_frontier.max(frontier);
+                match r {
+                    StepResult::Success { .. } => {
+                        self.end(Verdict::Accept);
+                        vec.push(r);
+                        return vec;
+                    }
+                    // The rest of the batch is dropped along with the frontier
+                    StepResult::Rejected { .. } if self.abort_on_reject => {
+                        self.observer.exhausted();
+                        self.end(self.verdict_on_fail());
+                        vec.push(r);
+                        vec.push(StepResult::FailAll);
+                        return vec;
+                    }
+                    r => vec.push(r),
+                }
+            }
+        }
+    }
+}
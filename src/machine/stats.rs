@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+/// How much work a computation took so far
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunStats {
+    /// How many machines were advanced, whatever happened to them
+    pub steps: usize,
+    /// Steps where a machine applied the only transition available and went on running
+    pub det_steps: usize,
+    /// Steps where a machine split in many
+    pub splits: usize,
+    /// How many branches were created by splitting, the initial one excluded
+    pub created: usize,
+    /// How many branches stopped for the lack of transitions, for looping or in a reject state
+    pub failed: usize,
+    /// How many branches were cut by the step limit
+    pub undecided: usize,
+    /// The most branches waiting to be advanced at once. It depends on the order the branches
+    /// are advanced in, so the parallel runner may find a different one
+    pub peak_frontier: usize,
+    /// The most cells holding symbols alive at once across the tapes of the computation, the
+    /// ones shared by many branches counted once
+    pub peak_cells: usize,
+    /// The time spent stepping the computation
+    pub elapsed: Duration,
+}
@@ -0,0 +1,221 @@
+/*!
+This module defines the order in which the branches of the computation are explored.
+
+Every strategy is backed by a different container, the `MachineStore` hides which one is being
+used from the `NDTM`.
+
+The store can also remember the configurations it has been given, so that when two branches
+converge on the same configuration only one of them is explored.
+*/
+
+use crate::machine::TM;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+
+/// The order in which the pending machines are executed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SearchStrategy {
+    /// Always execute the machine with the lowest depth + estimated distance from acceptance
+    BestFirst,
+    /// Execute machines in the order they were created
+    BreadthFirst,
+    /// Always execute the most recently created machine
+    DepthFirst,
+    /// Depth first, but the machines going past a limit on the number of steps are discarded.
+    /// Once every machine within the limit has been explored, if some were discarded the limit
+    /// is raised by the given amount and the search restarts from the initial machine, which is
+    /// numbered 0 again. Branches are explored again at every restart, in exchange only the
+    /// machines of a single path are kept at once
+    IterativeDeepening(usize),
+}
+
+/// The struct used to queue machines for executions
+pub(super) struct MachineStore<'r, const W: usize> {
+    queue: Queue<'r, W>,
+    /// Hash of each configuration queued so far and the lowest depth it was seen at, None if
+    /// deduplication is disabled
+    visited: Option<HashMap<u64, usize>>,
+    /// How many machines were discarded for being duplicates
+    pub pruned: usize,
+}
+
+enum Queue<'r, const W: usize> {
+    BestFirst(BinaryHeap<TM<'r, W>>),
+    BreadthFirst(VecDeque<TM<'r, W>>),
+    DepthFirst(Vec<TM<'r, W>>),
+    IterativeDeepening {
+        stack: Vec<TM<'r, W>>,
+        /// A copy of the initial machine to restart from, None until it's queued
+        initial: Option<Box<TM<'r, W>>>,
+        limit: usize,
+        increment: usize,
+        /// Whether some machine went past the limit since the last restart
+        cut: bool,
+    },
+}
+
+impl<'r, const W: usize> MachineStore<'r, W> {
+    pub(super) fn new(strategy: SearchStrategy) -> Self {
+        MachineStore {
+            queue: Queue::new(strategy),
+            visited: None,
+            pruned: 0,
+        }
+    }
+
+    /// Start or stop discarding machines whose configuration was already queued
+    pub(super) fn deduplicate(&mut self, enable: bool) {
+        if !enable {
+            self.visited = None;
+        } else if self.visited.is_none() {
+            self.visited = Some(HashMap::new());
+        }
+    }
+
+    /// Add a machine to the queue, returns it back if it was discarded as a duplicate or for
+    /// going past the limit of iterative deepening.
+    ///
+    /// A configuration is only a duplicate if it was already seen at the same or a lower depth,
+    /// otherwise a branch cut by the step limit could hide one which would have been accepted
+    pub(super) fn push(&mut self, machine: TM<'r, W>) -> Option<TM<'r, W>> {
+        if let Some(visited) = &mut self.visited {
+            let key = fingerprint(&machine);
+            match visited.get(&key) {
+                Some(&depth) if depth <= machine.depth => {
+                    self.pruned += 1;
+                    return Some(machine);
+                }
+                _ => {
+                    visited.insert(key, machine.depth);
+                }
+            }
+        }
+        self.queue.push(machine)
+    }
+
+    /// Take the next machine to execute
+    pub(super) fn pop(&mut self) -> Option<TM<'r, W>> {
+        // The configurations seen before a restart are seen again
+        if self.queue.restart() {
+            if let Some(visited) = &mut self.visited {
+                visited.clear();
+            }
+        }
+        self.queue.pop()
+    }
+
+    /// How many machines are waiting to be executed
+    pub(super) fn len(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+/// Hash of the state, the non empty region of the tapes, the position of the heads and the
+/// transition the machine is about to apply
+fn fingerprint<const W: usize>(machine: &TM<'_, W>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    machine.state.hash(&mut hasher);
+    machine.tape.contents().hash(&mut hasher);
+    for tape in &machine.others {
+        tape.contents().hash(&mut hasher);
+    }
+    machine.instr_cache.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<'r, const W: usize> Queue<'r, W> {
+    fn new(strategy: SearchStrategy) -> Self {
+        match strategy {
+            SearchStrategy::BestFirst => Queue::BestFirst(BinaryHeap::new()),
+            SearchStrategy::BreadthFirst => Queue::BreadthFirst(VecDeque::new()),
+            SearchStrategy::DepthFirst => Queue::DepthFirst(Vec::new()),
+            SearchStrategy::IterativeDeepening(increment) => {
+                // A zero increment would never let the search past the initial machine
+                let increment = increment.max(1);
+                Queue::IterativeDeepening {
+                    stack: Vec::new(),
+                    initial: None,
+                    limit: increment,
+                    increment,
+                    cut: false,
+                }
+            }
+        }
+    }
+
+    /// Queue a machine, returns it back if it went past the limit of iterative deepening
+    fn push(&mut self, machine: TM<'r, W>) -> Option<TM<'r, W>> {
+        match self {
+            Queue::BestFirst(heap) => heap.push(machine),
+            Queue::BreadthFirst(queue) => queue.push_back(machine),
+            Queue::DepthFirst(stack) => stack.push(machine),
+            Queue::IterativeDeepening {
+                stack,
+                initial,
+                limit,
+                cut,
+                ..
+            } => {
+                if machine.depth > *limit {
+                    *cut = true;
+                    return Some(machine);
+                }
+                // The first machine queued is the initial one
+                let machine = match initial {
+                    Some(_) => machine,
+                    None => {
+                        let (copy, machine) = machine.fork();
+                        *initial = Some(Box::new(copy));
+                        machine
+                    }
+                };
+                stack.push(machine);
+            }
+        }
+        None
+    }
+
+    /// Start the search over with a higher limit if every machine within the current one was
+    /// explored and some went past it. Returns whether it did
+    fn restart(&mut self) -> bool {
+        let Queue::IterativeDeepening {
+            stack,
+            initial,
+            limit,
+            increment,
+            cut,
+        } = self
+        else {
+            return false;
+        };
+        if !stack.is_empty() || !*cut {
+            return false;
+        }
+        let Some((copy, machine)) = initial.take().map(|m| m.fork()) else {
+            return false;
+        };
+        *initial = Some(Box::new(copy));
+        *limit = limit.saturating_add(*increment);
+        *cut = false;
+        stack.push(machine);
+        true
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Queue::BestFirst(heap) => heap.len(),
+            Queue::BreadthFirst(queue) => queue.len(),
+            Queue::DepthFirst(stack) => stack.len(),
+            Queue::IterativeDeepening { stack, .. } => stack.len(),
+        }
+    }
+
+    fn pop(&mut self) -> Option<TM<'r, W>> {
+        match self {
+            Queue::BestFirst(heap) => heap.pop(),
+            Queue::BreadthFirst(queue) => queue.pop_front(),
+            Queue::DepthFirst(stack) | Queue::IterativeDeepening { stack, .. } => stack.pop(),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+/*!
+This module records a trace of the computation, one event for every transition applied by a
+machine, to follow what each branch did step by step.
+
+Tracing is off unless asked for: taking the snapshots of the tape costs much more than the steps
+themselves.
+*/
+
+use crate::machine::{Step, TM};
+use std::collections::HashMap;
+
+/// A transition applied by one of the machines of the computation
+#[derive(Clone, Debug)]
+pub struct TraceEvent {
+    /// The machine which applied the transition
+    pub machine: usize,
+    /// The machine it was split from, None for the initial one
+    pub parent: Option<usize>,
+    /// The position of the head of the first tape before the transition, 0 being the first
+    /// symbol of the input
+    pub head: isize,
+    /// The symbols of the first tape around the head before the transition, the one under the
+    /// head in the middle
+    pub window: Vec<u8>,
+    /// The state, the symbol read and the transition applied
+    pub step: Step,
+}
+
+/// The events recorded so far and what's needed to fill them in
+pub(crate) struct Tracer {
+    /// How many symbols on each side of the head are kept
+    radius: usize,
+    /// The machine each one was split from
+    parents: HashMap<usize, usize>,
+    events: Vec<TraceEvent>,
+}
+
+/// The head of a machine before it steps
+pub(crate) struct Snapshot {
+    depth: usize,
+    head: isize,
+    window: Vec<u8>,
+}
+
+impl Tracer {
+    pub(crate) fn new(radius: usize) -> Self {
+        Tracer {
+            radius,
+            parents: HashMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    pub(crate) fn radius(&self) -> usize {
+        self.radius
+    }
+
+    /// Remember where the children of a split come from
+    pub(crate) fn split(&mut self, source: usize, children: &[usize]) {
+        self.parents.extend(children.iter().map(|c| (*c, source)));
+    }
+
+    pub(crate) fn push(&mut self, mut event: TraceEvent) {
+        event.parent = self.parents.get(&event.machine).copied();
+        self.events.push(event);
+    }
+
+    pub(crate) fn take(&mut self) -> Vec<TraceEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+impl Snapshot {
+    /// Keep the `radius` symbols on each side of the head
+    pub(crate) fn take<const W: usize>(machine: &TM<'_, W>, radius: usize) -> Self {
+        Snapshot {
+            depth: machine.depth,
+            head: machine.tape.position(),
+            window: machine.tape.window(radius),
+        }
+    }
+
+    /// The event for the transition the machine applied since the snapshot was taken, if any.
+    /// The parent is filled in when the event is recorded
+    pub(crate) fn finish<const W: usize>(self, machine: &TM<'_, W>) -> Option<TraceEvent> {
+        if machine.depth == self.depth {
+            return None;
+        }
+        machine.history.last().map(|step| TraceEvent {
+            machine: machine.idx,
+            parent: None,
+            head: self.head,
+            window: self.window,
+            step: step.clone(),
+        })
+    }
+}
@@ -0,0 +1,60 @@
+//! A symbol table is serialized as the lists of its named states and symbols.
+
+use crate::names::SymbolTable;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Serialize, Deserialize)]
+struct StateName {
+    id: usize,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SymbolName {
+    id: u8,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Names {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    states: Vec<StateName>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    symbols: Vec<SymbolName>,
+}
+
+impl Serialize for SymbolTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Names {
+            states: self
+                .states()
+                .map(|(id, name)| StateName {
+                    id,
+                    name: name.to_string(),
+                })
+                .collect(),
+            symbols: self
+                .symbols()
+                .map(|(id, name)| SymbolName {
+                    id,
+                    name: name.to_string(),
+                })
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SymbolTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let names = Names::deserialize(deserializer)?;
+        let mut table = SymbolTable::new();
+        for s in names.states {
+            table.name_state(s.id, &s.name);
+        }
+        for s in names.symbols {
+            table.name_symbol(s.id, &s.name);
+        }
+        Ok(table)
+    }
+}
@@ -0,0 +1,110 @@
+/*!
+This module maps the names used in a machine description to the ids used internally.
+
+States are identified by a `usize` and symbols by a `u8`. Descriptions may instead use any name
+for a state and multi character names for symbols; the `SymbolTable` remembers which id was given
+to each name so that anything shown to the user can use the original names.
+
+Names which already are valid ids (numeric state names and single byte symbols) are mapped to
+themselves and aren't stored in the table.
+*/
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "serde")]
+mod document;
+
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    state_ids: BTreeMap<String, usize>,
+    state_names: BTreeMap<usize, String>,
+    symbol_ids: BTreeMap<String, u8>,
+    symbol_names: BTreeMap<u8, String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Give a name to a state, replacing any previous name of the state
+    pub fn name_state(&mut self, id: usize, name: &str) {
+        if let Some(old) = self.state_names.insert(id, name.to_string()) {
+            self.state_ids.remove(&old);
+        }
+        self.state_ids.insert(name.to_string(), id);
+    }
+
+    /// Give a name to a symbol, replacing any previous name of the symbol
+    pub fn name_symbol(&mut self, symb: u8, name: &str) {
+        if let Some(old) = self.symbol_names.insert(symb, name.to_string()) {
+            self.symbol_ids.remove(&old);
+        }
+        self.symbol_ids.insert(name.to_string(), symb);
+    }
+
+    /// The id of the state with the given name, numbers which weren't used as names of other
+    /// states are their own id
+    pub fn state(&self, name: &str) -> Option<usize> {
+        match self.state_ids.get(name) {
+            Some(id) => Some(*id),
+            None => name
+                .parse()
+                .ok()
+                .filter(|id| !self.state_names.contains_key(id)),
+        }
+    }
+
+    /// The symbol with the given name, single bytes which weren't used as names of other symbols
+    /// are their own id
+    pub fn symbol(&self, name: &str) -> Option<u8> {
+        match (self.symbol_ids.get(name), name.as_bytes()) {
+            (Some(symb), _) => Some(*symb),
+            (None, [b]) if !self.symbol_names.contains_key(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn state_name(&self, id: usize) -> String {
+        match self.state_names.get(&id) {
+            Some(name) => name.clone(),
+            None => id.to_string(),
+        }
+    }
+
+    pub fn symbol_name(&self, symb: u8) -> String {
+        match self.symbol_names.get(&symb) {
+            Some(name) => name.clone(),
+            None => (symb as char).to_string(),
+        }
+    }
+
+    /// Write a sequence of symbols using their names. If some symbol has a multi character
+    /// name they are separated by spaces
+    pub fn word(&self, symbols: &[u8]) -> String {
+        let sep = if self.symbol_names.is_empty() {
+            ""
+        } else {
+            " "
+        };
+        symbols
+            .iter()
+            .map(|s| self.symbol_name(*s))
+            .collect::<Vec<_>>()
+            .join(sep)
+    }
+
+    /// Named states, by id
+    pub fn states(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.state_names.iter().map(|(id, n)| (*id, n.as_str()))
+    }
+
+    /// Named symbols, by id
+    pub fn symbols(&self) -> impl Iterator<Item = (u8, &str)> {
+        self.symbol_names.iter().map(|(s, n)| (*s, n.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state_names.is_empty() && self.symbol_names.is_empty()
+    }
+}
@@ -0,0 +1,34 @@
+//! JSON and TOML descriptions, available with the `serde` feature.
+
+use crate::parser::{Description, ErrorKind, ParseError};
+
+pub fn from_json(description: &str) -> Result<Description, ParseError> {
+    serde_json::from_str(description).map_err(|e| ParseError {
+        line: e.line(),
+        column: e.column(),
+        kind: ErrorKind::Format(e.to_string()),
+    })
+}
+
+pub fn to_json(description: &Description) -> String {
+    serde_json::to_string_pretty(description).expect("Descriptions are always serializable")
+}
+
+pub fn from_toml(description: &str) -> Result<Description, ParseError> {
+    toml::from_str(description).map_err(|e| {
+        // TOML errors only carry the byte range of the offending input
+        let offset = e.span().map(|s| s.start).unwrap_or(0);
+        let before = &description[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = offset - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+        ParseError {
+            line,
+            column,
+            kind: ErrorKind::Format(e.message().to_string()),
+        }
+    })
+}
+
+pub fn to_toml(description: &Description) -> String {
+    toml::to_string(description).expect("Descriptions are always serializable")
+}
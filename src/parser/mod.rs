@@ -0,0 +1,626 @@
+/*!
+This module reads the description of a machine from its textual format.
+
+The description is split in sections, each introduced by a header line containing only the name
+of the section:
+- `tr`: one transition per line, `<state> <read> <write> <L|R|S> <next state>`
+- `acc`: one final state per line
+- `rej`: optional, one reject state per line, a branch entering one of them stops and rejects
+- `start`: optional, the initial state
+- `blank`: optional, the symbol filling the tape outside of the input, `_` if not given
+- `max`: the maximum number of steps of a branch
+- `tapes`: optional, the number of tapes, 1 if not given
+- `run`: optional and always the last section, one input word per line
+
+Sections other than `run` may appear more than once and in any order.
+
+States are either numbers, used as they are, or names made of letters, digits and underscores
+starting with a letter or underscore. Named states are numbered after the whole description is
+read, avoiding the numbers already in use. The initial state is always number 0: it's the state
+given in `start`, or state `0` if it's used, or the first state of the first transition.
+
+Symbols are either single bytes, used as they are, or longer names which are given one of the
+bytes not already in use. The names of the sections can't be used as names of states or symbols.
+In a transition the read symbol may also be `*`, matching any symbol, or a class of symbols
+between brackets such as `[ab]`, separated by commas if some of them have longer names as in
+`[one,two]`; the written symbol may be `*`, writing back the symbol which was read. A specific
+rule overrides the class ones, which override the ones for any symbol.
+
+With more than one tape a transition lists the symbols read on each tape, then the ones written
+and the movements, as in `<state> <read 1> <read 2> <write 1> <write 2> <dir 1> <dir 2> <next>`
+for two tapes. Such transitions only take plain symbols. The input is written on the first tape,
+the others start empty.
+An input word is either a single token, read one byte at a time unless it's the name of a symbol,
+or a sequence of symbols separated by whitespace.
+
+Tokens are separated by whitespace. Blank lines and lines starting with `#` are ignored, a `#`
+token after the last field of a line starts a comment extending to the end of the line. Since
+all the lines after `run` are input words the headers aren't recognised there.
+
+Errors carry the position of the offending token and what was expected in its place.
+
+With the `serde` feature descriptions can also be read from and written to JSON and TOML.
+*/
+
+use crate::names::SymbolTable;
+use crate::rules::{Pattern, RuleStore, Write};
+use crate::tape::Movement;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::BufRead;
+
+#[cfg(feature = "serde")]
+mod formats;
+
+#[cfg(feature = "serde")]
+pub use crate::parser::formats::{from_json, from_toml, to_json, to_toml};
+
+/// A machine read from its description
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Description {
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub rules: RuleStore,
+    pub max_steps: usize,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::rules::document::symbol", default = "default_blank")
+    )]
+    pub blank: u8,
+    /// The words listed in the `run` section
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            with = "crate::rules::document::words",
+            default,
+            skip_serializing_if = "Vec::is_empty"
+        )
+    )]
+    pub inputs: Vec<Vec<u8>>,
+    /// The names of the states and symbols
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "SymbolTable::is_empty")
+    )]
+    pub names: SymbolTable,
+}
+
+fn default_blank() -> u8 {
+    b'_'
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    /// The line of the error, starting from 1
+    pub line: usize,
+    /// The column of the first character of the offending token, starting from 1
+    pub column: usize,
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The line ended before the given field
+    MissingField(&'static str),
+    /// The line has more fields than expected
+    ExtraField(String),
+    /// The token isn't a valid state number or name
+    BadState(String),
+    /// The token isn't a valid number
+    BadNumber(String),
+    /// The token isn't one of `L`, `R` or `S`
+    BadDirection(String),
+    /// There are no bytes left to give to a new symbol name
+    TooManySymbols(String),
+    /// An input word uses a symbol which doesn't appear in the description
+    UnknownSymbol(String),
+    /// The class of symbols is empty or contains a symbol which can't be named
+    BadClass(String),
+    /// A pattern in a transition of a machine with many tapes
+    MultiTapePattern(String),
+    /// The declared initial state isn't state `0`, but state `0` is also used
+    StartConflict(String),
+    /// A state or symbol is named after a section
+    ReservedName(String),
+    /// A line which isn't a header before the first section
+    OutsideSection,
+    /// The description couldn't be read
+    Io(std::io::Error),
+    /// A JSON or TOML description is malformed
+    Format(String),
+}
+
+impl ErrorKind {
+    /// Describes the token which was expected where the error happened
+    pub fn expected(&self) -> &'static str {
+        match self {
+            ErrorKind::MissingField(field) => field,
+            ErrorKind::ExtraField(_) => "the end of the line",
+            ErrorKind::BadState(_) => "a state number or name",
+            ErrorKind::BadNumber(_) => "a number",
+            ErrorKind::BadDirection(_) => "a direction (L, R or S)",
+            ErrorKind::TooManySymbols(_) => "at most 256 different symbols",
+            ErrorKind::UnknownSymbol(_) => "a symbol used by the transitions",
+            ErrorKind::BadClass(_) => "a class of symbols such as [ab]",
+            ErrorKind::MultiTapePattern(_) => "a single symbol, patterns need a single tape",
+            ErrorKind::StartConflict(_) => "state 0 to be the initial state",
+            ErrorKind::ReservedName(_) => "a name other than the one of a section",
+            ErrorKind::OutsideSection => "a section header",
+            ErrorKind::Io(_) => "a readable line",
+            ErrorKind::Format(_) => "a valid description",
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: expected {}",
+            self.line,
+            self.column,
+            self.kind.expected()
+        )?;
+        match &self.kind {
+            ErrorKind::MissingField(_) => write!(f, ", found the end of the line"),
+            ErrorKind::ExtraField(t)
+            | ErrorKind::BadState(t)
+            | ErrorKind::BadNumber(t)
+            | ErrorKind::BadDirection(t)
+            | ErrorKind::TooManySymbols(t)
+            | ErrorKind::UnknownSymbol(t)
+            | ErrorKind::BadClass(t)
+            | ErrorKind::MultiTapePattern(t)
+            | ErrorKind::StartConflict(t)
+            | ErrorKind::ReservedName(t) => write!(f, ", found `{}`", t),
+            ErrorKind::OutsideSection => Ok(()),
+            ErrorKind::Io(e) => write!(f, " ({})", e),
+            ErrorKind::Format(msg) => write!(f, " ({})", msg),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Copy, Clone)]
+enum Section {
+    None,
+    Transitions,
+    Accepting,
+    Rejecting,
+    Start,
+    Blank,
+    MaxSteps,
+    Tapes,
+    Run,
+}
+
+impl Section {
+    fn from_header(name: &str) -> Option<Self> {
+        match name {
+            "tr" => Some(Section::Transitions),
+            "acc" => Some(Section::Accepting),
+            "rej" => Some(Section::Rejecting),
+            "start" => Some(Section::Start),
+            "blank" => Some(Section::Blank),
+            "max" => Some(Section::MaxSteps),
+            "tapes" => Some(Section::Tapes),
+            "run" => Some(Section::Run),
+            _ => None,
+        }
+    }
+}
+
+/// The whitespace separated tokens of a line, each with the column it starts at
+struct Tokens<'l> {
+    line: &'l str,
+    line_no: usize,
+    offset: usize,
+}
+
+impl<'l> Tokens<'l> {
+    fn new(line: &'l str, line_no: usize) -> Self {
+        Tokens {
+            line,
+            line_no,
+            offset: 0,
+        }
+    }
+
+    fn error(&self, column: usize, kind: ErrorKind) -> ParseError {
+        ParseError {
+            line: self.line_no,
+            column,
+            kind,
+        }
+    }
+
+    /// The next token and its column, or an error saying `field` was missing
+    fn next(&mut self, field: &'static str) -> Result<(usize, &'l str), ParseError> {
+        let rest = &self.line[self.offset..];
+        let start = self.offset + (rest.len() - rest.trim_start().len());
+        let rest = &self.line[start..];
+        let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error(start + 1, ErrorKind::MissingField(field)));
+        }
+        self.offset = start + len;
+        Ok((start + 1, &rest[..len]))
+    }
+
+    fn state(&mut self, names: &SymbolTable) -> Result<usize, ParseError> {
+        let (col, tok) = self.next("a state number or name")?;
+        names
+            .state(tok)
+            .ok_or_else(|| self.error(col, ErrorKind::BadState(tok.to_string())))
+    }
+
+    fn number(&mut self) -> Result<usize, ParseError> {
+        let (col, tok) = self.next("a number")?;
+        tok.parse()
+            .map_err(|_| self.error(col, ErrorKind::BadNumber(tok.to_string())))
+    }
+
+    fn symbol(&mut self, names: &SymbolTable) -> Result<u8, ParseError> {
+        let (col, tok) = self.next("a symbol")?;
+        names
+            .symbol(tok)
+            .ok_or_else(|| self.error(col, ErrorKind::UnknownSymbol(tok.to_string())))
+    }
+
+    /// The symbols read by a transition, see the module documentation
+    fn pattern(&mut self, names: &SymbolTable) -> Result<Pattern, ParseError> {
+        let (col, tok) = self.next("a symbol")?;
+        if tok == "*" {
+            return Ok(Pattern::Any);
+        }
+        let Some(members) = class_members(tok) else {
+            return names
+                .symbol(tok)
+                .map(Pattern::Symbol)
+                .ok_or_else(|| self.error(col, ErrorKind::UnknownSymbol(tok.to_string())));
+        };
+        let class = members
+            .into_iter()
+            .map(|m| names.symbol(m))
+            .collect::<Option<BTreeSet<u8>>>()
+            .filter(|class| !class.is_empty())
+            .ok_or_else(|| self.error(col, ErrorKind::BadClass(tok.to_string())))?;
+        Ok(Pattern::Class(class))
+    }
+
+    /// The symbol written by a transition, `*` to write the one which was read
+    fn write(&mut self, names: &SymbolTable) -> Result<Write, ParseError> {
+        let (col, tok) = self.next("a symbol")?;
+        if tok == "*" {
+            return Ok(Write::Same);
+        }
+        names
+            .symbol(tok)
+            .map(Write::Symbol)
+            .ok_or_else(|| self.error(col, ErrorKind::UnknownSymbol(tok.to_string())))
+    }
+
+    /// A symbol in a transition of a machine with many tapes
+    fn plain_symbol(&mut self, names: &SymbolTable) -> Result<u8, ParseError> {
+        let (col, tok) = self.next("a symbol")?;
+        if tok == "*" || class_members(tok).is_some() {
+            return Err(self.error(col, ErrorKind::MultiTapePattern(tok.to_string())));
+        }
+        names
+            .symbol(tok)
+            .ok_or_else(|| self.error(col, ErrorKind::UnknownSymbol(tok.to_string())))
+    }
+
+    fn direction(&mut self) -> Result<Movement, ParseError> {
+        let (col, tok) = self.next("a direction (L, R or S)")?;
+        match tok {
+            "R" => Ok(Movement::Right),
+            "L" => Ok(Movement::Left),
+            "S" => Ok(Movement::Stay),
+            _ => Err(self.error(col, ErrorKind::BadDirection(tok.to_string()))),
+        }
+    }
+
+    /// An input word, see the module documentation
+    fn word(&mut self, names: &SymbolTable) -> Result<Vec<u8>, ParseError> {
+        let mut symbols = Vec::new();
+        while let Ok((col, tok)) = self.next("") {
+            if tok.starts_with('#') {
+                break;
+            }
+            symbols.push((col, tok));
+        }
+        if let [(_, tok)] = symbols[..] {
+            return Ok(match names.symbol(tok) {
+                Some(s) if tok.len() > 1 => vec![s],
+                _ => tok.as_bytes().to_vec(),
+            });
+        }
+        symbols
+            .into_iter()
+            .map(|(col, tok)| {
+                names
+                    .symbol(tok)
+                    .ok_or_else(|| self.error(col, ErrorKind::UnknownSymbol(tok.to_string())))
+            })
+            .collect()
+    }
+
+    /// Fails if there are tokens left on the line, other than a comment
+    fn end(&mut self) -> Result<(), ParseError> {
+        match self.next("") {
+            Ok((_, tok)) if tok.starts_with('#') => Ok(()),
+            Ok((col, tok)) => Err(self.error(col, ErrorKind::ExtraField(tok.to_string()))),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// The section introduced by the line, if it's a header
+    fn header(&self) -> Option<Section> {
+        let mut copy = Tokens::new(self.line, self.line_no);
+        let (_, name) = copy.next("").ok()?;
+        let section = Section::from_header(name)?;
+        copy.end().ok()?;
+        Some(section)
+    }
+}
+
+/// Lines with content (neither blank nor comments nor headers), along with their number and the
+/// section they're in
+fn content(lines: &[String]) -> impl Iterator<Item = (Section, usize, &str)> {
+    let mut section = Section::None;
+    lines.iter().enumerate().filter_map(move |(idx, line)| {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            return None;
+        }
+        if let Section::Run = section {
+            return Some((section, idx + 1, line.as_str()));
+        }
+        match Tokens::new(line, idx + 1).header() {
+            Some(s) => {
+                section = s;
+                None
+            }
+            None => Some((section, idx + 1, line.as_str())),
+        }
+    })
+}
+
+/// The names of the symbols of a class, if the token is one
+fn class_members(tok: &str) -> Option<Vec<&str>> {
+    let inner = tok.strip_prefix('[')?.strip_suffix(']')?;
+    if inner.contains(',') {
+        Some(inner.split(',').collect())
+    } else {
+        Some(
+            inner
+                .char_indices()
+                .map(|(i, c)| &inner[i..i + c.len_utf8()])
+                .collect(),
+        )
+    }
+}
+
+fn is_state_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// The number of tapes, given in the last `tapes` section
+fn count_tapes(lines: &[String]) -> Result<usize, ParseError> {
+    let mut tapes = 1;
+    for (section, line_no, line) in content(lines) {
+        if let Section::Tapes = section {
+            let mut tokens = Tokens::new(line, line_no);
+            let (col, tok) = tokens.next("a number")?;
+            tapes = tok
+                .parse()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| tokens.error(col, ErrorKind::BadNumber(tok.to_string())))?;
+            tokens.end()?;
+        }
+    }
+    Ok(tapes)
+}
+
+/// Collect the names used for states and symbols and give each one an id
+fn declare(lines: &[String], tapes: usize) -> Result<SymbolTable, ParseError> {
+    // Names in order of appearance, with their position for symbols
+    let mut states: Vec<&str> = Vec::new();
+    let mut start: Option<(usize, usize, &str)> = None;
+    let mut symbols: Vec<(usize, usize, &str)> = Vec::new();
+
+    for (section, line_no, line) in content(lines) {
+        let mut tokens = Tokens::new(line, line_no);
+        // Only the fields are read, a comment can only follow the last one and anything wrong
+        // with the line is reported when it's parsed
+        let count = match section {
+            Section::Transitions => 3 * tapes + 2,
+            Section::Accepting | Section::Rejecting | Section::Start | Section::Blank => 1,
+            _ => 0,
+        };
+        let fields: Vec<(usize, &str)> = std::iter::from_fn(|| tokens.next("").ok())
+            .take(count)
+            .collect();
+        let (state_fields, symbol_fields): (Vec<usize>, Vec<usize>) = match section {
+            Section::Transitions => (vec![0, 3 * tapes + 1], (1..=2 * tapes).collect()),
+            Section::Accepting | Section::Rejecting | Section::Start => (vec![0], vec![]),
+            Section::Blank => (vec![], vec![0]),
+            _ => (vec![], vec![]),
+        };
+        let reserved = |col, name: &str| match Section::from_header(name) {
+            Some(_) => Err(tokens.error(col, ErrorKind::ReservedName(name.to_string()))),
+            None => Ok(()),
+        };
+        for &i in &state_fields {
+            if let Some(&(col, name)) = fields.get(i) {
+                reserved(col, name)?;
+                if let Section::Start = section {
+                    start = Some((line_no, col, name));
+                }
+                if !states.contains(&name) {
+                    states.push(name);
+                }
+            }
+        }
+        for &i in &symbol_fields {
+            let Some(&(col, tok)) = fields.get(i) else {
+                continue;
+            };
+            let members = match class_members(tok) {
+                Some(members) => members,
+                None => vec![tok],
+            };
+            for name in members {
+                reserved(col, name)?;
+                if name.len() > 1 && !symbols.iter().any(|(_, _, n)| *n == name) {
+                    symbols.push((line_no, col, name));
+                }
+            }
+        }
+    }
+
+    let mut names = SymbolTable::new();
+    let mut used: Vec<usize> = states.iter().filter_map(|s| s.parse().ok()).collect();
+    let named = states.iter().filter(|s| is_state_name(s));
+
+    let start = match start {
+        Some((line, column, name)) if name != "0" => {
+            if used.contains(&0) || !is_state_name(name) {
+                return Err(ParseError {
+                    line,
+                    column,
+                    kind: ErrorKind::StartConflict(name.to_string()),
+                });
+            }
+            Some(name)
+        }
+        Some(_) => None,
+        None if used.contains(&0) => None,
+        None => states.first().copied().filter(|s| is_state_name(s)),
+    };
+    if let Some(name) = start {
+        names.name_state(0, name);
+        used.push(0);
+    }
+    let mut next_id = 0;
+    for name in named.filter(|n| Some(**n) != start) {
+        while used.contains(&next_id) {
+            next_id += 1;
+        }
+        names.name_state(next_id, name);
+        used.push(next_id);
+    }
+
+    // Any byte in the description might be a symbol, either on its own or in an input word
+    let taken: BTreeSet<u8> = lines.iter().flat_map(|l| l.bytes()).collect();
+    let mut free = (128..=255u8).chain(1..128).filter(|b| !taken.contains(b));
+    for (line, column, name) in symbols {
+        match free.next() {
+            Some(b) => names.name_symbol(b, name),
+            None => {
+                return Err(ParseError {
+                    line,
+                    column,
+                    kind: ErrorKind::TooManySymbols(name.to_string()),
+                })
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Read a machine description
+pub fn parse<R: BufRead>(reader: R) -> Result<Description, ParseError> {
+    let mut lines = Vec::new();
+    for (idx, line) in reader.lines().enumerate() {
+        lines.push(line.map_err(|e| ParseError {
+            line: idx + 1,
+            column: 1,
+            kind: ErrorKind::Io(e),
+        })?);
+    }
+    let tapes = count_tapes(&lines)?;
+    let names = declare(&lines, tapes)?;
+
+    let mut rules = RuleStore::new();
+    rules.set_tapes(tapes);
+    let mut max_steps: usize = 0;
+    let mut blank = default_blank();
+    let mut inputs = Vec::new();
+
+    for (section, line_no, line) in content(&lines) {
+        let mut tokens = Tokens::new(line, line_no);
+        match section {
+            Section::Transitions if tapes > 1 => {
+                let state_in = tokens.state(&names)?;
+                let mut symbols = Vec::with_capacity(2 * tapes);
+                for _ in 0..2 * tapes {
+                    symbols.push(tokens.plain_symbol(&names)?);
+                }
+                let mut dirs = Vec::with_capacity(tapes);
+                for _ in 0..tapes {
+                    dirs.push(tokens.direction()?);
+                }
+                let state_out = tokens.state(&names)?;
+                tokens.end()?;
+                let (reads, writes) = symbols.split_at(tapes);
+                rules.add_multi_rule(state_in, reads, writes, state_out, &dirs);
+            }
+            Section::Transitions => {
+                let state_in = tokens.state(&names)?;
+                let read = tokens.pattern(&names)?;
+                let write = tokens.write(&names)?;
+                let dir = tokens.direction()?;
+                let state_out = tokens.state(&names)?;
+                tokens.end()?;
+                rules.add_pattern(state_in, read, write, state_out, dir);
+            }
+            Section::Accepting => {
+                rules.add_final(tokens.state(&names)?);
+                tokens.end()?;
+            }
+            Section::Rejecting => {
+                rules.add_reject(tokens.state(&names)?);
+                tokens.end()?;
+            }
+            Section::Start => {
+                // Already taken care of when numbering the states
+                tokens.state(&names)?;
+                tokens.end()?;
+            }
+            Section::Blank => {
+                blank = tokens.symbol(&names)?;
+                tokens.end()?;
+            }
+            Section::MaxSteps => {
+                max_steps = tokens.number()?;
+                tokens.end()?;
+            }
+            // Already read before the transitions
+            Section::Tapes => {}
+            Section::Run => inputs.push(tokens.word(&names)?),
+            Section::None => return Err(tokens.error(1, ErrorKind::OutsideSection)),
+        }
+    }
+
+    Ok(Description {
+        rules,
+        max_steps,
+        blank,
+        inputs,
+        names,
+    })
+}
+
+/// Read an input word given outside of the description, such as on the standard input
+pub fn parse_word(line: &str, names: &SymbolTable) -> Result<Vec<u8>, ParseError> {
+    let mut tokens = Tokens::new(line, 1);
+    if line.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    tokens.word(names)
+}
@@ -0,0 +1,189 @@
+/*!
+Static checks of a rule set, looking for the mistakes which would otherwise only show up as wrong
+verdicts, and for the points where the machine is nondeterministic
+*/
+
+use crate::names::SymbolTable;
+use crate::rules::{Input, Output, RuleStore};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A likely mistake in a rule set
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Warning {
+    /// A state which isn't final or reject has no transitions, machines entering it always fail
+    DeadEnd(usize),
+    /// The state can't be reached from the initial state 0
+    Unreachable(usize),
+    /// A final state has transitions, they are never taken since machines stop there
+    FinalWithTransitions(usize),
+    /// The symbol is written by some transition but no transition reads it
+    NeverRead(u8),
+}
+
+impl Warning {
+    /// Describe the problem using the names in `names`
+    pub fn message(&self, names: &SymbolTable) -> String {
+        match self {
+            Warning::DeadEnd(s) => format!(
+                "state {} has no transitions and isn't final or reject",
+                names.state_name(*s)
+            ),
+            Warning::Unreachable(s) => format!(
+                "state {} can't be reached from the initial state",
+                names.state_name(*s)
+            ),
+            Warning::FinalWithTransitions(s) => format!(
+                "final state {} has transitions which are never taken",
+                names.state_name(*s)
+            ),
+            Warning::NeverRead(symb) => format!(
+                "symbol {} is written but never read",
+                names.symbol_name(*symb)
+            ),
+        }
+    }
+}
+
+impl RuleStore {
+    /// Look for likely mistakes in the rules, see `Warning`.
+    ///
+    /// The warnings are sorted by kind, then by state or symbol
+    pub fn analyze(&self) -> Vec<Warning> {
+        let rules = self.expanded();
+        let states = self.states();
+        let mut sources: BTreeSet<usize> = rules.keys().map(|i| i.state).collect();
+        sources.extend(self.multi.keys());
+        let mut warnings = Vec::new();
+
+        warnings.extend(
+            states
+                .iter()
+                .filter(|s| !sources.contains(s) && !self.is_final(**s) && !self.is_reject(**s))
+                .map(|s| Warning::DeadEnd(*s)),
+        );
+
+        let reachable = self.reachable(&rules);
+        warnings.extend(
+            states
+                .iter()
+                .filter(|s| !reachable.contains(s))
+                .map(|s| Warning::Unreachable(*s)),
+        );
+
+        warnings.extend(
+            sources
+                .iter()
+                .filter(|s| self.is_final(**s))
+                .map(|s| Warning::FinalWithTransitions(*s)),
+        );
+
+        let mut read: BTreeSet<u8> = rules.keys().map(|i| i.symb).collect();
+        let mut written: BTreeSet<u8> = rules
+            .values()
+            .flat_map(|o| o.transitions().iter().map(|t| t.symb))
+            .collect();
+        for (reads, trs) in self.multi.values().flatten() {
+            read.extend(reads);
+            written.extend(trs.iter().flat_map(|t| t.symbs.iter()));
+        }
+        warnings.extend(written.difference(&read).map(|s| Warning::NeverRead(*s)));
+        warnings
+    }
+
+    /// The states which can be reached from the initial state, the search goes on past final
+    /// and reject states since their transitions are reported separately
+    fn reachable(&self, rules: &BTreeMap<Input, Output>) -> BTreeSet<usize> {
+        let mut reached = BTreeSet::from([0]);
+        let mut queue = vec![0];
+        while let Some(state) = queue.pop() {
+            let inputs = Input { state, symb: 0 }..=Input {
+                state,
+                symb: u8::MAX,
+            };
+            let single = rules
+                .range(inputs)
+                .flat_map(|(_, output)| output.transitions().iter().map(|t| t.state));
+            let multi = self
+                .multi
+                .get(&state)
+                .into_iter()
+                .flat_map(|rules| rules.values().flatten().map(|t| t.state));
+            for next in single.chain(multi).collect::<Vec<_>>() {
+                if reached.insert(next) {
+                    queue.push(next);
+                }
+            }
+        }
+        reached
+    }
+}
+
+/// A state and the symbols under the heads with more than one transition
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Choice {
+    pub state: usize,
+    /// The symbol under the head of each tape
+    pub symbols: Vec<u8>,
+    /// How many transitions are available
+    pub fan_out: usize,
+}
+
+/// Where a machine is nondeterministic
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Determinism {
+    /// Every nondeterministic choice, sorted by state and symbol
+    pub choices: Vec<Choice>,
+    /// True if no choice is ever made
+    pub deterministic: bool,
+}
+
+impl Determinism {
+    /// The largest number of branches a machine can split into at once, 1 for deterministic
+    /// machines
+    pub fn max_branching(&self) -> usize {
+        self.choices.iter().map(|c| c.fan_out).max().unwrap_or(1)
+    }
+}
+
+impl RuleStore {
+    /// List the states and symbols read with more than one transition
+    pub fn determinism(&self) -> Determinism {
+        let mut choices: Vec<Choice> = self
+            .expanded()
+            .iter()
+            .filter_map(|(input, output)| match output {
+                Output::Multi(trs) => Some(Choice {
+                    state: input.state,
+                    symbols: vec![input.symb],
+                    fan_out: trs.len(),
+                }),
+                _ => None,
+            })
+            .collect();
+        for (state, rules) in self.multi.iter() {
+            choices.extend(
+                rules
+                    .iter()
+                    .filter(|(_, trs)| trs.len() > 1)
+                    .map(|(reads, trs)| Choice {
+                        state: *state,
+                        symbols: reads.clone(),
+                        fan_out: trs.len(),
+                    }),
+            );
+        }
+        Determinism {
+            deterministic: choices.is_empty(),
+            choices,
+        }
+    }
+
+    /// True if every state has at most one transition for the symbols it reads
+    pub fn is_deterministic(&self) -> bool {
+        !self
+            .expanded()
+            .values()
+            .any(|output| matches!(output, Output::Multi(_)))
+            && self.multi.values().flatten().all(|(_, trs)| trs.len() < 2)
+    }
+}
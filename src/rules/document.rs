@@ -0,0 +1,325 @@
+/*!
+This module defines how rule sets are serialized.
+
+The lookup tables of a `RuleStore` aren't a convenient format to read or write by hand, so a
+store is serialized as the flat list of its rules, the ones for a specific symbol first, plus
+the sets of final and reject states. Symbols are written as single character strings.
+
+As in the textual format `*` stands for any symbol when read and for the symbol which was read
+when written, and a class of symbols is written as a string of symbols between brackets, so a
+`*` symbol is always written as a number.
+
+Machines with many tapes also have the number of tapes and their rules, which read and write a
+string with a symbol for each tape and have a list of movements.
+*/
+
+use crate::rules::{Pattern, RuleStore, Write};
+use crate::tape::Movement;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeSet;
+
+/// A single rule: reading `read` in state `from` the machine writes `write`, moves in `dir` and
+/// goes to state `to`
+#[derive(Serialize, Deserialize)]
+struct Rule {
+    from: usize,
+    #[serde(with = "pattern")]
+    read: Pattern,
+    #[serde(with = "write")]
+    write: Write,
+    dir: Movement,
+    to: usize,
+}
+
+/// A rule of a machine with many tapes, with a symbol and a movement for each tape
+#[derive(Serialize, Deserialize)]
+struct MultiRule {
+    from: usize,
+    #[serde(with = "tuple")]
+    read: Vec<u8>,
+    #[serde(with = "tuple")]
+    write: Vec<u8>,
+    dir: Vec<Movement>,
+    to: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RuleSet {
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    tapes: usize,
+    rules: Vec<Rule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    multi_rules: Vec<MultiRule>,
+    accept: BTreeSet<usize>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    reject: BTreeSet<usize>,
+}
+
+fn one() -> usize {
+    1
+}
+
+fn is_one(n: &usize) -> bool {
+    *n == 1
+}
+
+impl From<&RuleStore> for RuleSet {
+    fn from(store: &RuleStore) -> Self {
+        let mut rules = Vec::new();
+        for (input, output) in store.rules.iter() {
+            rules.extend(output.transitions().iter().map(|tr| Rule {
+                from: input.state,
+                read: Pattern::Symbol(input.symb),
+                write: Write::Symbol(tr.symb),
+                dir: tr.dir,
+                to: tr.state,
+            }));
+        }
+        for (state, patterns) in store.patterns.iter() {
+            rules.extend(patterns.iter().map(|p| Rule {
+                from: *state,
+                read: p.read.clone(),
+                write: p.write,
+                dir: p.dir,
+                to: p.state,
+            }));
+        }
+        let mut multi_rules = Vec::new();
+        for (state, by_read) in store.multi.iter() {
+            for (read, trs) in by_read {
+                multi_rules.extend(trs.iter().map(|tr| MultiRule {
+                    from: *state,
+                    read: read.clone(),
+                    write: tr.symbs.clone(),
+                    dir: tr.dirs.clone(),
+                    to: tr.state,
+                }));
+            }
+        }
+        RuleSet {
+            tapes: store.tapes,
+            rules,
+            multi_rules,
+            accept: store.fin_s.clone(),
+            reject: store.rej_s.clone(),
+        }
+    }
+}
+
+impl TryFrom<RuleSet> for RuleStore {
+    type Error = String;
+
+    fn try_from(set: RuleSet) -> Result<Self, Self::Error> {
+        if set.tapes == 0 {
+            return Err("a machine needs at least one tape".to_string());
+        }
+        let mut store = RuleStore::new();
+        store.set_tapes(set.tapes);
+        for r in set.rules {
+            store.add_pattern(r.from, r.read, r.write, r.to, r.dir);
+        }
+        for r in set.multi_rules {
+            if [r.read.len(), r.write.len(), r.dir.len()] != [set.tapes; 3] {
+                return Err(format!(
+                    "the rule from state {} to state {} needs a symbol to read, one to write and \
+                     a movement for each of the {} tapes",
+                    r.from, r.to, set.tapes
+                ));
+            }
+            store.add_multi_rule(r.from, &r.read, &r.write, r.to, &r.dir);
+        }
+        for s in set.accept {
+            store.add_final(s);
+        }
+        for s in set.reject {
+            store.add_reject(s);
+        }
+        Ok(store)
+    }
+}
+
+impl Serialize for RuleStore {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RuleSet::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RuleStore {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        RuleSet::deserialize(deserializer)
+            .and_then(|set| RuleStore::try_from(set).map_err(serde::de::Error::custom))
+    }
+}
+
+/// A symbol, or a word, as written in a document
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Text {
+    Str(String),
+    Byte(u8),
+    Bytes(Vec<u8>),
+}
+
+/// Symbols which can be written as themselves, the others are written as numbers
+fn printable(symb: u8) -> bool {
+    symb.is_ascii_graphic()
+}
+
+/// Symbols which can be written as themselves outside of words, `*` has a special meaning there
+fn literal(symb: u8) -> bool {
+    printable(symb) && symb != b'*'
+}
+
+/// (De)serialize a symbol as a string made of that single byte, or as a number if it isn't
+/// printable
+pub(crate) mod symbol {
+    use super::{literal, Text};
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(symb: &u8, serializer: S) -> Result<S::Ok, S::Error> {
+        if literal(*symb) {
+            serializer.serialize_str(&(*symb as char).to_string())
+        } else {
+            serializer.serialize_u8(*symb)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+        from_text::<D>(Text::deserialize(deserializer)?)
+    }
+
+    pub(super) fn from_text<'de, D: Deserializer<'de>>(text: Text) -> Result<u8, D::Error> {
+        match text {
+            Text::Byte(b) => Ok(b),
+            Text::Str(s) if s.len() == 1 => Ok(s.as_bytes()[0]),
+            Text::Str(s) => Err(D::Error::custom(format!(
+                "expected a single byte symbol, found `{}`",
+                s
+            ))),
+            Text::Bytes(_) => Err(D::Error::custom("expected a single symbol, found a list")),
+        }
+    }
+}
+
+/// (De)serialize the symbols read by a rule: a single symbol, `*` or a class such as `[ab]`,
+/// written as a list of numbers if some of its symbols aren't printable
+mod pattern {
+    use super::{literal, Text};
+    use crate::rules::Pattern;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(read: &Pattern, serializer: S) -> Result<S::Ok, S::Error> {
+        match read {
+            Pattern::Symbol(symb) => super::symbol::serialize(symb, serializer),
+            Pattern::Any => serializer.serialize_str("*"),
+            Pattern::Class(set) if set.iter().all(|s| literal(*s)) => {
+                let class: String = set.iter().map(|s| *s as char).collect();
+                serializer.serialize_str(&format!("[{}]", class))
+            }
+            Pattern::Class(set) => set.serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pattern, D::Error> {
+        let class = match Text::deserialize(deserializer)? {
+            Text::Byte(b) => return Ok(Pattern::Symbol(b)),
+            Text::Str(s) if s == "*" => return Ok(Pattern::Any),
+            Text::Str(s) if s.len() == 1 => return Ok(Pattern::Symbol(s.as_bytes()[0])),
+            Text::Str(s) => match s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+                Some(inner) => inner.as_bytes().to_vec(),
+                None => {
+                    return Err(D::Error::custom(format!(
+                        "expected a symbol, `*` or a class of symbols, found `{}`",
+                        s
+                    )))
+                }
+            },
+            Text::Bytes(b) => b,
+        };
+        if class.is_empty() {
+            return Err(D::Error::custom("expected a class of symbols, found `[]`"));
+        }
+        Ok(Pattern::Class(class.into_iter().collect()))
+    }
+}
+
+/// (De)serialize the symbol written by a rule, `*` to write the one which was read
+mod write {
+    use super::Text;
+    use crate::rules::Write;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(write: &Write, serializer: S) -> Result<S::Ok, S::Error> {
+        match write {
+            Write::Symbol(symb) => super::symbol::serialize(symb, serializer),
+            Write::Same => serializer.serialize_str("*"),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Write, D::Error> {
+        match Text::deserialize(deserializer)? {
+            Text::Str(s) if s == "*" => Ok(Write::Same),
+            text => super::symbol::from_text::<D>(text).map(Write::Symbol),
+        }
+    }
+}
+
+/// (De)serialize the symbols of a rule for many tapes as a string, or as a list of numbers if
+/// some of them aren't printable
+mod tuple {
+    use super::{printable, Text};
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(symbols: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if symbols.iter().all(|s| printable(*s)) {
+            serializer.serialize_str(&String::from_utf8_lossy(symbols))
+        } else {
+            serde::Serialize::serialize(symbols, serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        match Text::deserialize(deserializer)? {
+            Text::Str(s) => Ok(s.into_bytes()),
+            Text::Bytes(b) => Ok(b),
+            Text::Byte(_) => Err(D::Error::custom("expected a symbol for each tape")),
+        }
+    }
+}
+
+/// (De)serialize a list of words as strings, or as lists of numbers if some of their symbols
+/// aren't printable
+pub(crate) mod words {
+    use super::{printable, Text};
+    use serde::de::Error;
+    use serde::ser::SerializeSeq;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(words: &[Vec<u8>], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(words.len()))?;
+        for w in words {
+            if w.iter().all(|s| printable(*s)) {
+                seq.serialize_element(&String::from_utf8_lossy(w))?;
+            } else {
+                seq.serialize_element(w)?;
+            }
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<u8>>, D::Error> {
+        Vec::<Text>::deserialize(deserializer)?
+            .into_iter()
+            .map(|t| match t {
+                Text::Str(s) => Ok(s.into_bytes()),
+                Text::Bytes(b) => Ok(b),
+                Text::Byte(_) => Err(D::Error::custom("expected a word, found a number")),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,138 @@
+/*!
+Export of the transition graph in the Graphviz DOT language
+*/
+
+use crate::names::SymbolTable;
+use crate::rules::{Input, Output, RuleStore, Transition};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// The transitions going from a state to another
+#[derive(Default)]
+struct Edge {
+    labels: Vec<String>,
+    /// Some of the transitions are alternatives of a nondeterministic choice
+    multi: bool,
+}
+
+impl RuleStore {
+    /// Describe the transition graph in the DOT language, using the names in `names`.
+    ///
+    /// Every state is a node, final states are double circled. All the transitions from a state
+    /// to another are merged in a single edge labelled `read/write,dir` once per transition,
+    /// rules for many symbols are labelled with their pattern; edges which are part of a
+    /// nondeterministic choice are drawn in red
+    pub fn to_dot(&self, names: &SymbolTable) -> String {
+        let mut edges: BTreeMap<(usize, usize), Edge> = BTreeMap::new();
+        for (input, output) in self.rules.iter() {
+            let multi = matches!(output, Output::Multi(_));
+            for tr in output.transitions() {
+                let edge = edges.entry((input.state, tr.state)).or_default();
+                edge.labels.push(label(input.symb, tr, names));
+                edge.multi |= multi;
+            }
+        }
+        for (&state, patterns) in self.patterns.iter() {
+            for p in patterns {
+                // The rule is part of a choice if some symbol it applies to has more than one
+                // transition, not counting those overridden by a specific rule
+                let multi = (0..=u8::MAX).any(|symb| {
+                    p.read.matches(symb)
+                        && !self.rules.contains_key(&Input { state, symb })
+                        && self.transitions(state, symb).len() > 1
+                });
+                let edge = edges.entry((state, p.state)).or_default();
+                edge.labels.push(format!(
+                    "{}/{},{}",
+                    p.read.describe(names),
+                    p.write.describe(names),
+                    p.dir.as_str()
+                ));
+                edge.multi |= multi;
+            }
+        }
+        // Rules for many tapes are labelled with the action on each tape, separated by bars
+        for (&state, by_read) in self.multi.iter() {
+            for (reads, trs) in by_read {
+                for tr in trs {
+                    let label: Vec<String> = (0..reads.len())
+                        .map(|i| {
+                            format!(
+                                "{}/{},{}",
+                                names.symbol_name(reads[i]),
+                                names.symbol_name(tr.symbs[i]),
+                                tr.dirs[i].as_str()
+                            )
+                        })
+                        .collect();
+                    let edge = edges.entry((state, tr.state)).or_default();
+                    edge.labels.push(label.join(" | "));
+                    edge.multi |= trs.len() > 1;
+                }
+            }
+        }
+
+        let mut dot = String::from("digraph {\n    rankdir=LR;\n");
+        dot.push_str("    start [shape=point];\n");
+        for state in self.states() {
+            let shape = if self.is_final(state) {
+                "doublecircle"
+            } else {
+                "circle"
+            };
+            let _ = writeln!(
+                dot,
+                "    {} [label={}, shape={}];",
+                state,
+                quote(&names.state_name(state)),
+                shape
+            );
+        }
+        dot.push_str("    start -> 0;\n");
+        for ((from, to), edge) in edges {
+            let _ = write!(
+                dot,
+                "    {} -> {} [label={}",
+                from,
+                to,
+                quote(&edge.labels.join("\n"))
+            );
+            if edge.multi {
+                dot.push_str(", color=red, fontcolor=red, style=bold");
+            }
+            dot.push_str("];\n");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn label(read: u8, tr: &Transition, names: &SymbolTable) -> String {
+    format!(
+        "{}/{},{}",
+        names.symbol_name(read),
+        names.symbol_name(tr.symb),
+        tr.dir.as_str()
+    )
+}
+
+/// A DOT string literal, lines are separated with `\n` escapes
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\\\x{:02x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
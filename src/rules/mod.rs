@@ -1,14 +1,36 @@
 /*!
-This module provides a way to define the transitions between the states of the turing machine
-
+This module provides a way to define the transitions between the states of the turing machine.
 
+Besides the rules for a specific symbol, a state may have rules for a class of symbols or for any
+symbol. When reading a symbol the specific rules come first, then the rules of the classes the
+symbol belongs to and at last the rules for any symbol: only the rules of the first kind found
+are applied, so a specific rule overrides the more general ones.
 */
 
+use crate::rules::pattern::PatternRule;
+use crate::rules::table::TransitionTable;
 use crate::tape::Movement;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, BTreeSet};
+use std::sync::OnceLock;
+
+mod analysis;
+#[cfg(feature = "serde")]
+pub(crate) mod document;
+mod dot;
+mod multi;
+mod pattern;
+mod table;
+
+pub use crate::rules::analysis::{Choice, Determinism, Warning};
+pub use crate::rules::multi::MultiTransition;
+pub use crate::rules::pattern::{Pattern, Write};
 
 /// The current state of the machine and tape
+#[derive(Copy, Clone)]
 pub struct Input {
     pub state: usize,
     pub symb: u8,
@@ -18,7 +40,7 @@ impl Eq for Input {}
 
 impl PartialEq<Self> for Input {
     fn eq(&self, other: &Self) -> bool {
-        self == other
+        self.cmp(other) == Ordering::Equal
     }
 }
 
@@ -32,7 +54,7 @@ impl Ord for Input {
     fn cmp(&self, other: &Self) -> Ordering {
         match self.state.cmp(&other.state) {
             Ordering::Equal => self.symb.cmp(&other.symb),
-            r @ _ => r,
+            r => r,
         }
     }
 }
@@ -44,22 +66,43 @@ pub enum Output {
     Multi(Vec<Transition>),
 }
 
+impl Output {
+    fn from_transitions(mut trs: Vec<Transition>) -> Self {
+        match trs.len() {
+            0 => Output::None,
+            1 => Output::Simple(trs.pop().unwrap()),
+            _ => Output::Multi(trs),
+        }
+    }
+
+    /// The alternatives, empty if there's no transition
+    pub fn transitions(&self) -> &[Transition] {
+        match self {
+            Output::None => &[],
+            Output::Simple(tr) => std::slice::from_ref(tr),
+            Output::Multi(trs) => trs,
+        }
+    }
+}
+
 impl Clone for Output {
     fn clone(&self) -> Self {
         match self {
             Output::None => Output::None,
-            Output::Simple(trs) => Output::Simple(trs.clone()),
+            Output::Simple(trs) => Output::Simple(*trs),
             Output::Multi(vtrs) => Output::Multi(vtrs.clone()),
         }
     }
 }
 
 /// How the state of the turing machine and tape will change
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Transition {
     /// The output state
     pub state: usize,
     /// The symbol to overwrite the input symbol with
+    #[cfg_attr(feature = "serde", serde(with = "document::symbol"))]
     pub symb: u8,
     /// The movement of the tape following the write
     pub dir: Movement,
@@ -67,18 +110,62 @@ pub struct Transition {
 
 pub struct RuleStore {
     rules: BTreeMap<Input, Output>,
+    /// The rules for classes of symbols and for any symbol, by state
+    patterns: BTreeMap<usize, Vec<PatternRule>>,
+    tapes: usize,
+    /// The rules of machines with many tapes, by state and then by the symbols read
+    multi: BTreeMap<usize, BTreeMap<Vec<u8>, Vec<MultiTransition>>>,
     states_backtrace: BTreeMap<usize, Vec<usize>>,
-    states_dist: BTreeMap<usize, usize>,
+    /// Distance of each state from the closest final state, computed lazily on the first
+    /// query after the rule set was last modified
+    states_dist: OnceLock<BTreeMap<usize, usize>>,
+    /// Same as `states_dist` but for the closest reject state
+    reject_dist: OnceLock<BTreeMap<usize, usize>>,
+    /// Same as `states_dist` but for each (state, symbol) pair with a rule
+    symbols_dist: OnceLock<BTreeMap<Input, usize>>,
+    /// The rules compiled for fast lookup, also computed lazily
+    table: OnceLock<TransitionTable>,
     fin_s: BTreeSet<usize>,
+    rej_s: BTreeSet<usize>,
+}
+
+/// How the distance of a machine from acceptance is estimated when choosing which branch to
+/// explore next
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Heuristic {
+    /// Only take into account the current state of the machine
+    State,
+    /// Take into account both the state and the symbol under the head, only counting the
+    /// transitions actually available for that symbol
+    Symbol,
+}
+
+impl Default for RuleStore {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RuleStore {
     pub fn new() -> Self {
         RuleStore {
             rules: Default::default(),
+            patterns: Default::default(),
+            tapes: 1,
+            multi: Default::default(),
             states_backtrace: Default::default(),
             states_dist: Default::default(),
+            reject_dist: Default::default(),
+            symbols_dist: Default::default(),
+            table: Default::default(),
             fin_s: Default::default(),
+            rej_s: Default::default(),
+        }
+    }
+
+    pub fn builder() -> RuleStoreBuilder {
+        RuleStoreBuilder {
+            store: RuleStore::new(),
         }
     }
 
@@ -95,20 +182,14 @@ impl RuleStore {
             symb: symb_out,
             dir,
         };
-        let mut entry = self
-            .rules
+        self.rules
             .entry(Input {
                 state: state_in,
                 symb: symb_in,
             })
             .and_modify(|e| match e {
                 Output::None => *e = Output::Simple(new_t),
-                Output::Simple(tr) => {
-                    let mut v = Vec::new();
-                    v.push(*tr);
-                    v.push(new_t);
-                    *e = Output::Multi(v)
-                }
+                Output::Simple(tr) => *e = Output::Multi(vec![*tr, new_t]),
                 Output::Multi(v) => v.push(new_t),
             })
             .or_insert(Output::Simple(new_t));
@@ -116,47 +197,310 @@ impl RuleStore {
             .entry(state_out)
             .and_modify(|v| v.push(state_in))
             .or_insert(vec![state_in]);
+        self.states_dist = OnceLock::new();
+        self.reject_dist = OnceLock::new();
+        self.symbols_dist = OnceLock::new();
+        self.table = OnceLock::new();
+    }
+
+    /// Add a rule which may apply to many symbols, see the module documentation for which
+    /// rules are chosen when more than one apply
+    pub fn add_pattern(
+        &mut self,
+        state_in: usize,
+        read: Pattern,
+        write: Write,
+        state_out: usize,
+        dir: Movement,
+    ) {
+        if let Pattern::Symbol(symb) = read {
+            return self.add_rule(state_in, symb, write.resolve(symb), state_out, dir);
+        }
+        self.patterns
+            .entry(state_in)
+            .or_default()
+            .push(PatternRule {
+                read,
+                write,
+                state: state_out,
+                dir,
+            });
+        self.states_backtrace
+            .entry(state_out)
+            .and_modify(|v| v.push(state_in))
+            .or_insert(vec![state_in]);
+        self.states_dist = OnceLock::new();
+        self.reject_dist = OnceLock::new();
+        self.symbols_dist = OnceLock::new();
+        self.table = OnceLock::new();
     }
 
     pub fn get(&self, state_in: usize, symb_in: u8) -> Output {
-        self.rules
-            .get(&Input {
-                state: state_in,
-                symb: symb_in,
-            })
-            .unwrap_or(&Output::None)
-            .clone()
+        let input = Input {
+            state: state_in,
+            symb: symb_in,
+        };
+        if let Some(output) = self.rules.get(&input) {
+            return output.clone();
+        }
+        let Some(patterns) = self.patterns.get(&state_in) else {
+            return Output::None;
+        };
+        let matching = |class: bool| {
+            patterns
+                .iter()
+                .filter(|p| matches!(p.read, Pattern::Class(_)) == class)
+                .filter(|p| p.read.matches(symb_in))
+                .map(|p| Transition {
+                    state: p.state,
+                    symb: p.write.resolve(symb_in),
+                    dir: p.dir,
+                })
+                .collect::<Vec<_>>()
+        };
+        let classes = matching(true);
+        if classes.is_empty() {
+            Output::from_transitions(matching(false))
+        } else {
+            Output::from_transitions(classes)
+        }
+    }
+
+    /// The rules for every (state, symbol) pair with a transition, with the rules for classes
+    /// of symbols and for any symbol applied to each symbol they match
+    fn expanded(&self) -> Cow<'_, BTreeMap<Input, Output>> {
+        if self.patterns.is_empty() {
+            return Cow::Borrowed(&self.rules);
+        }
+        let mut rules = self.rules.clone();
+        for &state in self.patterns.keys() {
+            for symb in 0..=u8::MAX {
+                let input = Input { state, symb };
+                if rules.contains_key(&input) {
+                    continue;
+                }
+                match self.get(state, symb) {
+                    Output::None => {}
+                    output => {
+                        rules.insert(input, output);
+                    }
+                }
+            }
+        }
+        Cow::Owned(rules)
+    }
+
+    /// The transitions available reading `symb_in` in `state_in`, empty if there's none.
+    ///
+    /// Unlike `get` nothing is copied, the rules are compiled into a table on the first call
+    /// after they were last modified
+    pub fn transitions(&self, state_in: usize, symb_in: u8) -> &[Transition] {
+        self.table
+            .get_or_init(|| TransitionTable::compile(self))
+            .get(state_in, symb_in)
     }
 
     pub fn add_final(&mut self, final_state: usize) {
         self.fin_s.insert(final_state);
+        self.states_dist = OnceLock::new();
+        self.reject_dist = OnceLock::new();
+        self.symbols_dist = OnceLock::new();
     }
 
     pub fn is_final(&self, state: usize) -> bool {
         self.fin_s.contains(&state)
     }
 
-    pub fn compute_dist(&mut self) {
-        return;
-        let mut queue = &mut self.fin_s;
-        let mut next_q = BTreeSet::new();
-        let mut tmp_q = BTreeSet::new();
+    /// A machine entering a reject state stops there without accepting, as if no final state
+    /// could be reached from it
+    pub fn add_reject(&mut self, reject_state: usize) {
+        self.rej_s.insert(reject_state);
+        self.states_dist = OnceLock::new();
+        self.reject_dist = OnceLock::new();
+        self.symbols_dist = OnceLock::new();
+    }
+
+    pub fn is_reject(&self, state: usize) -> bool {
+        self.rej_s.contains(&state)
+    }
+
+    /// Every state mentioned by the rules, including the initial state 0 and the final and
+    /// reject states
+    fn states(&self) -> BTreeSet<usize> {
+        let mut states: BTreeSet<usize> = BTreeSet::from([0]);
+        states.extend(self.fin_s.iter().chain(self.rej_s.iter()));
+        states.extend(self.rules.keys().map(|i| i.state));
+        states.extend(self.patterns.keys());
+        states.extend(self.multi.keys());
+        states.extend(self.states_backtrace.keys());
+        states
+    }
+
+    /// Breadth first visit of the transition graph, going backwards from the `targets`.
+    ///
+    /// States from which no target is reached are left out of the returned map, as are the
+    /// reject states which aren't targets since no transition is taken from them
+    fn compute_dist(&self, targets: &BTreeSet<usize>) -> BTreeMap<usize, usize> {
+        let mut states_dist = BTreeMap::new();
+        let mut queue: BTreeSet<usize> = targets.clone();
         let mut dist = 0;
         while !queue.is_empty() {
+            let mut next_q = BTreeSet::new();
             for &i in queue.iter() {
-                self.states_dist.entry(i).or_insert(dist);
-                next_q.extend(self.states_backtrace.get(&i).unwrap_or(&vec![]));
+                states_dist.insert(i, dist);
+            }
+            for i in queue {
+                if let Some(prev) = self.states_backtrace.get(&i) {
+                    next_q.extend(
+                        prev.iter()
+                            .filter(|s| !states_dist.contains_key(*s) && !self.is_reject(**s)),
+                    );
+                }
             }
             dist += 1;
-            tmp_q = next_q;
-            queue = &mut tmp_q;
-            next_q = BTreeSet::new();
+            queue = next_q;
+        }
+        states_dist
+    }
+
+    /// Returns the minimum number of transitions needed to go from the state to a final state,
+    /// or None if no final state can be reached from it
+    pub fn distance(&self, state: usize) -> Option<usize> {
+        self.states_dist
+            .get_or_init(|| self.compute_dist(&self.fin_s))
+            .get(&state)
+            .copied()
+    }
+
+    /// Returns the minimum number of transitions needed to go from the state to a reject
+    /// state, or None if no reject state can be reached from it
+    pub fn reject_distance(&self, state: usize) -> Option<usize> {
+        self.reject_dist
+            .get_or_init(|| self.compute_dist(&self.rej_s))
+            .get(&state)
+            .copied()
+    }
+
+    /// For every (state, symbol) pair with a rule, the distance is one more than the
+    /// closest state reachable with one of its transitions.
+    ///
+    /// Pairs whose transitions all lead to states which can't reach a final state are left out
+    fn compute_symbols_dist(&self) -> BTreeMap<Input, usize> {
+        let mut symbols_dist = BTreeMap::new();
+        for (input, output) in self.expanded().iter() {
+            if self.is_reject(input.state) {
+                continue;
+            }
+            let best = match output {
+                Output::None => None,
+                Output::Simple(tr) => self.distance(tr.state),
+                Output::Multi(trs) => trs.iter().filter_map(|t| self.distance(t.state)).min(),
+            };
+            if let Some(d) = best {
+                symbols_dist.insert(
+                    Input {
+                        state: input.state,
+                        symb: input.symb,
+                    },
+                    d + 1,
+                );
+            }
+        }
+        symbols_dist
+    }
+
+    /// Returns the minimum number of transitions needed to reach a final state when reading
+    /// `symb` in `state`, or None if there's no such path.
+    ///
+    /// Final states always have distance 0
+    pub fn symbol_distance(&self, state: usize, symb: u8) -> Option<usize> {
+        if self.is_final(state) {
+            return Some(0);
+        }
+        self.symbols_dist
+            .get_or_init(|| self.compute_symbols_dist())
+            .get(&Input { state, symb })
+            .copied()
+    }
+
+    /// Estimate the distance from acceptance of a machine in `state` reading `symb`.
+    ///
+    /// Machines with many tapes only take their state into account
+    pub fn estimate(&self, heuristic: Heuristic, state: usize, symb: u8) -> Option<usize> {
+        match heuristic {
+            Heuristic::Symbol if self.tapes == 1 => self.symbol_distance(state, symb),
+            _ => self.distance(state),
         }
     }
+}
+
+/// Chainable construction of a `RuleStore`
+pub struct RuleStoreBuilder {
+    store: RuleStore,
+}
+
+impl RuleStoreBuilder {
+    /// See `RuleStore::add_rule`
+    pub fn rule(
+        mut self,
+        state_in: usize,
+        symb_in: u8,
+        symb_out: u8,
+        state_out: usize,
+        dir: Movement,
+    ) -> Self {
+        self.store
+            .add_rule(state_in, symb_in, symb_out, state_out, dir);
+        self
+    }
+
+    /// See `RuleStore::add_pattern`
+    pub fn pattern(
+        mut self,
+        state_in: usize,
+        read: Pattern,
+        write: Write,
+        state_out: usize,
+        dir: Movement,
+    ) -> Self {
+        self.store
+            .add_pattern(state_in, read, write, state_out, dir);
+        self
+    }
+
+    /// See `RuleStore::set_tapes`
+    pub fn tapes(mut self, tapes: usize) -> Self {
+        self.store.set_tapes(tapes);
+        self
+    }
+
+    /// See `RuleStore::add_multi_rule`
+    pub fn multi_rule(
+        mut self,
+        state_in: usize,
+        reads: &[u8],
+        writes: &[u8],
+        state_out: usize,
+        dirs: &[Movement],
+    ) -> Self {
+        self.store
+            .add_multi_rule(state_in, reads, writes, state_out, dirs);
+        self
+    }
+
+    /// See `RuleStore::add_final`
+    pub fn accept(mut self, final_state: usize) -> Self {
+        self.store.add_final(final_state);
+        self
+    }
+
+    /// See `RuleStore::add_reject`
+    pub fn reject(mut self, reject_state: usize) -> Self {
+        self.store.add_reject(reject_state);
+        self
+    }
 
-    /// Returns the distance from the state to a final state
-    pub fn distance(&self, state: usize) -> usize {
-        //*self.states_dist.get(&state).unwrap()
-        0
+    pub fn build(self) -> RuleStore {
+        self.store
     }
 }
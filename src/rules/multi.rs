@@ -0,0 +1,94 @@
+/*!
+Rules of machines with more than one tape.
+
+Each rule reads a tuple of symbols, one under each head, and writes a symbol and moves the head of
+every tape. Single tape machines keep using the rules for a single symbol, which also support
+patterns, while the rules of machines with many tapes only take plain symbols.
+*/
+
+use crate::rules::RuleStore;
+use crate::tape::Movement;
+use std::sync::OnceLock;
+
+/// How the state and the tapes of a machine with many tapes change
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MultiTransition {
+    /// The output state
+    pub state: usize,
+    /// The symbols to write, one for each tape
+    pub symbs: Vec<u8>,
+    /// The movements of the heads following the write, one for each tape
+    pub dirs: Vec<Movement>,
+}
+
+impl RuleStore {
+    /// The number of tapes of the machine
+    pub fn tapes(&self) -> usize {
+        self.tapes
+    }
+
+    /// Set the number of tapes, before adding any rule for many tapes. Machines with many tapes
+    /// only use the rules added with `add_multi_rule`.
+    ///
+    /// # Panics
+    ///
+    /// If `tapes` is 0 or some rule for a different number of tapes was already added
+    pub fn set_tapes(&mut self, tapes: usize) {
+        assert!(tapes > 0, "A machine needs at least one tape");
+        assert!(
+            self.multi.is_empty() || tapes == self.tapes,
+            "The number of tapes can't change once rules for many tapes were added"
+        );
+        self.tapes = tapes;
+    }
+
+    /// Add a rule reading the symbols in `reads`, one for each tape. With a single tape this is
+    /// the same as `add_rule`.
+    ///
+    /// # Panics
+    ///
+    /// If `reads`, `writes` and `dirs` don't have one element for each tape
+    pub fn add_multi_rule(
+        &mut self,
+        state_in: usize,
+        reads: &[u8],
+        writes: &[u8],
+        state_out: usize,
+        dirs: &[Movement],
+    ) {
+        assert!(
+            reads.len() == self.tapes && writes.len() == self.tapes && dirs.len() == self.tapes,
+            "A rule needs a symbol to read, one to write and a movement for each of the {} tapes",
+            self.tapes
+        );
+        if self.tapes == 1 {
+            return self.add_rule(state_in, reads[0], writes[0], state_out, dirs[0]);
+        }
+        self.multi
+            .entry(state_in)
+            .or_default()
+            .entry(reads.to_vec())
+            .or_default()
+            .push(MultiTransition {
+                state: state_out,
+                symbs: writes.to_vec(),
+                dirs: dirs.to_vec(),
+            });
+        self.states_backtrace
+            .entry(state_out)
+            .and_modify(|v| v.push(state_in))
+            .or_insert(vec![state_in]);
+        self.states_dist = OnceLock::new();
+        self.reject_dist = OnceLock::new();
+        self.symbols_dist = OnceLock::new();
+    }
+
+    /// The transitions available reading the symbols in `reads` in `state_in`, one for each
+    /// tape, empty if there's none
+    pub fn multi_transitions(&self, state_in: usize, reads: &[u8]) -> &[MultiTransition] {
+        self.multi
+            .get(&state_in)
+            .and_then(|rules| rules.get(reads))
+            .map_or(&[], |trs| trs.as_slice())
+    }
+}
@@ -0,0 +1,77 @@
+use crate::names::SymbolTable;
+use crate::tape::Movement;
+use std::collections::BTreeSet;
+
+/// The symbols a rule applies to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    Symbol(u8),
+    /// Any of the symbols in the set
+    Class(BTreeSet<u8>),
+    /// Any symbol at all
+    Any,
+}
+
+impl Pattern {
+    pub fn matches(&self, symb: u8) -> bool {
+        match self {
+            Pattern::Symbol(s) => *s == symb,
+            Pattern::Class(set) => set.contains(&symb),
+            Pattern::Any => true,
+        }
+    }
+
+    /// Write the pattern as in the textual description: `*` for any symbol and the symbols of a
+    /// class between brackets, separated by commas if some of them have a longer name
+    pub fn describe(&self, names: &SymbolTable) -> String {
+        match self {
+            Pattern::Symbol(s) => names.symbol_name(*s),
+            Pattern::Any => "*".to_string(),
+            Pattern::Class(set) => {
+                let symbols: Vec<String> = set.iter().map(|s| names.symbol_name(*s)).collect();
+                let sep = if symbols.iter().all(|s| s.chars().count() == 1) {
+                    ""
+                } else {
+                    ","
+                };
+                format!("[{}]", symbols.join(sep))
+            }
+        }
+    }
+}
+
+/// What a rule writes on the tape
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Write {
+    Symbol(u8),
+    /// The symbol which was read, leaving the tape unchanged
+    Same,
+}
+
+impl Write {
+    /// The symbol written after reading `read`
+    pub fn resolve(self, read: u8) -> u8 {
+        match self {
+            Write::Symbol(s) => s,
+            Write::Same => read,
+        }
+    }
+
+    /// Write the output as in the textual description, `*` for the symbol which was read
+    pub fn describe(&self, names: &SymbolTable) -> String {
+        match self {
+            Write::Symbol(s) => names.symbol_name(*s),
+            Write::Same => "*".to_string(),
+        }
+    }
+}
+
+/// A rule reading a class of symbols or any symbol, only used when no rule is given for the
+/// specific symbol under the head
+#[derive(Clone, Debug)]
+pub(crate) struct PatternRule {
+    pub read: Pattern,
+    pub write: Write,
+    pub state: usize,
+    pub dir: Movement,
+}
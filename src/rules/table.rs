@@ -0,0 +1,87 @@
+use crate::rules::{RuleStore, Transition};
+use std::collections::{BTreeSet, HashMap};
+
+/// Above this many state ids for every state with transitions, the rows are found through a
+/// hash map instead of a vector indexed by state
+const SPARSE_RATIO: usize = 4;
+
+/// The transitions of a rule set laid out for fast lookup: all of them are stored in a single
+/// arena, ordered by state and symbol, and a `[row][symbol]` table gives where the transitions
+/// of each pair start. Those of a pair end where the ones of the next pair start.
+///
+/// Only the states with transitions get a row, numbered in increasing order of state
+pub(crate) struct TransitionTable {
+    rows: Rows,
+    starts: Vec<u32>,
+    arena: Vec<Transition>,
+}
+
+/// Where the row of each state with transitions is
+enum Rows {
+    /// Indexed by state, `u32::MAX` for the states without a row. Used when the states are
+    /// numbered densely
+    Dense(Vec<u32>),
+    Sparse(HashMap<usize, u32>),
+}
+
+impl Rows {
+    fn get(&self, state: usize) -> Option<usize> {
+        match self {
+            Rows::Dense(rows) => rows
+                .get(state)
+                .filter(|r| **r != u32::MAX)
+                .map(|r| *r as usize),
+            Rows::Sparse(rows) => rows.get(&state).map(|r| *r as usize),
+        }
+    }
+}
+
+impl TransitionTable {
+    pub(crate) fn compile(store: &RuleStore) -> Self {
+        let states: BTreeSet<usize> = store
+            .rules
+            .keys()
+            .map(|i| i.state)
+            .chain(store.patterns.keys().copied())
+            .collect();
+        let ids = states.last().map_or(0, |s| s + 1);
+        let rows = if ids <= states.len() * SPARSE_RATIO + 256 {
+            let mut rows = vec![u32::MAX; ids];
+            for (row, state) in states.iter().enumerate() {
+                rows[*state] = row as u32;
+            }
+            Rows::Dense(rows)
+        } else {
+            Rows::Sparse(
+                states
+                    .iter()
+                    .enumerate()
+                    .map(|(row, state)| (*state, row as u32))
+                    .collect(),
+            )
+        };
+        let mut starts = Vec::with_capacity(states.len() * 256 + 1);
+        let mut arena = Vec::new();
+        for &state in &states {
+            for symb in 0..=u8::MAX {
+                starts.push(arena.len() as u32);
+                arena.extend_from_slice(store.get(state, symb).transitions());
+            }
+        }
+        starts.push(arena.len() as u32);
+        TransitionTable {
+            rows,
+            starts,
+            arena,
+        }
+    }
+
+    /// The transitions available reading `symb` in `state`
+    pub(crate) fn get(&self, state: usize, symb: u8) -> &[Transition] {
+        let Some(row) = self.rows.get(state) else {
+            return &[];
+        };
+        let idx = row * 256 + symb as usize;
+        &self.arena[self.starts[idx] as usize..self.starts[idx + 1] as usize]
+    }
+}
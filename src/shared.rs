@@ -0,0 +1,113 @@
+/*!
+This module defines the pointers used to share data between the branches of a computation, such
+as the cells of a tape or the history of a machine.
+
+By default they are the single threaded `Rc` and `RefCell`. With the `parallel` feature they are
+replaced by `Arc` and `RwLock`, so that machines can be moved to other threads, at the price of
+atomic reference counting and locking on every access.
+*/
+
+use std::ops::{Deref, DerefMut};
+
+/// A reference counted pointer
+#[cfg(not(feature = "parallel"))]
+pub type Ptr<T> = std::rc::Rc<T>;
+/// A reference counted pointer
+#[cfg(feature = "parallel")]
+pub type Ptr<T> = std::sync::Arc<T>;
+
+#[cfg(not(feature = "parallel"))]
+type Lock<T> = std::cell::RefCell<T>;
+#[cfg(feature = "parallel")]
+type Lock<T> = std::sync::RwLock<T>;
+
+/// A reference counted pointer to a mutable value
+#[derive(Debug)]
+pub struct Shared<T>(Ptr<Lock<T>>);
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Shared(self.0.clone())
+    }
+}
+
+impl<T> Shared<T> {
+    pub fn new(value: T) -> Self {
+        Shared(Ptr::new(Lock::new(value)))
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+impl<T> Shared<T> {
+    pub fn read(&self) -> impl Deref<Target = T> + '_ {
+        self.0.borrow()
+    }
+
+    pub fn write(&self) -> impl DerefMut<Target = T> + '_ {
+        self.0.borrow_mut()
+    }
+
+    /// Take the value if this is the only pointer to it, otherwise give the pointer back
+    pub fn try_unwrap(self) -> Result<T, Self> {
+        Ptr::try_unwrap(self.0)
+            .map(Lock::into_inner)
+            .map_err(Shared)
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<T> Shared<T> {
+    pub fn read(&self) -> impl Deref<Target = T> + '_ {
+        self.0.read().unwrap()
+    }
+
+    pub fn write(&self) -> impl DerefMut<Target = T> + '_ {
+        self.0.write().unwrap()
+    }
+
+    /// Take the value if this is the only pointer to it, otherwise give the pointer back
+    pub fn try_unwrap(self) -> Result<T, Self> {
+        Ptr::try_unwrap(self.0)
+            .map(|lock| lock.into_inner().unwrap())
+            .map_err(Shared)
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+type Count = std::cell::Cell<usize>;
+#[cfg(feature = "parallel")]
+type Count = std::sync::atomic::AtomicUsize;
+
+/// A reference counted number, such as how many cells the tapes of a computation hold
+#[derive(Clone, Debug, Default)]
+pub struct Counter(Ptr<Count>);
+
+#[cfg(not(feature = "parallel"))]
+impl Counter {
+    pub fn add(&self) {
+        self.0.set(self.0.get() + 1)
+    }
+
+    pub fn sub(&self) {
+        self.0.set(self.0.get() - 1)
+    }
+
+    pub fn get(&self) -> usize {
+        self.0.get()
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl Counter {
+    pub fn add(&self) {
+        self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn sub(&self) {
+        self.0.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> usize {
+        self.0.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
@@ -33,15 +33,9 @@ impl<const W: usize> Cache<W> {
     /// the cache can be copied when splitting a Tape (splitting a tape doesn't change the active
     /// side nor the cursor position)
     pub fn new(current: [u8; W], left: [u8; W]) -> Self {
-        let mut buffer = [0u8; W];
-        buffer[0..W].copy_from_slice(&left);
-        let b_l = buffer.clone();
-        buffer[0..W].copy_from_slice(&current);
-        let b_r = buffer.clone();
-
         Cache {
-            buffer_l: b_l,
-            buffer_r: b_r,
+            buffer_l: left,
+            buffer_r: current,
             cursor: 0,
             current: Side::Right,
             dirty: (false, false),
@@ -122,8 +116,8 @@ impl<const W: usize> Cache<W> {
                 } else {
                     self.current = Side::Left;
                     self.cursor = W - 1;
-                    let o_right = self.buffer_r.clone();
-                    self.buffer_r = self.buffer_l.clone();
+                    let o_right = self.buffer_r;
+                    self.buffer_r = self.buffer_l;
                     self.buffer_l[0..W].copy_from_slice(new_content);
                     if self.dirty.1 {
                         self.dirty.1 = self.dirty.0;
@@ -142,8 +136,8 @@ impl<const W: usize> Cache<W> {
                 } else {
                     self.current = Side::Right;
                     self.cursor = 0;
-                    let o_left = self.buffer_l.clone();
-                    self.buffer_l = self.buffer_r.clone();
+                    let o_left = self.buffer_l;
+                    self.buffer_l = self.buffer_r;
                     self.buffer_r[0..W].copy_from_slice(new_content);
                     if self.dirty.0 {
                         self.dirty.0 = self.dirty.1;
@@ -162,20 +156,34 @@ impl<const W: usize> Cache<W> {
         }
     }
 
+    /// The position of the cursor inside the active cell
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// The up to date contents of the active cell and of the other one, along with the side the
+    /// other one is on
+    pub fn buffers(&self) -> ([u8; W], (Side, [u8; W])) {
+        match self.current {
+            Side::Left => (self.buffer_l, (Side::Right, self.buffer_r)),
+            Side::Right => (self.buffer_r, (Side::Left, self.buffer_l)),
+        }
+    }
+
     /// Returns the contents of the current cell to write to the cell
     /// If the cell hadn't been written to it returns None
     pub fn flush_current(&self) -> Option<[u8; W]> {
         match self.current {
             Side::Left => {
                 if self.dirty.0 {
-                    Some(self.buffer_l.clone())
+                    Some(self.buffer_l)
                 } else {
                     None
                 }
             }
             Side::Right => {
                 if self.dirty.1 {
-                    Some(self.buffer_r.clone())
+                    Some(self.buffer_r)
                 } else {
                     None
                 }
@@ -183,6 +191,11 @@ impl<const W: usize> Cache<W> {
         }
     }
 
+    /// Forget that the cells were written to, once their contents were flushed
+    pub fn mark_clean(&mut self) {
+        self.dirty = (false, false);
+    }
+
     /// Returns which cell is in the cache (to the left or the right of current) and the
     /// up to date contents
     pub fn flush_other(&self) -> (Side, Option<[u8; W]>) {
@@ -190,7 +203,7 @@ impl<const W: usize> Cache<W> {
             Side::Left => (
                 Side::Right,
                 if self.dirty.1 {
-                    Some(self.buffer_r.clone())
+                    Some(self.buffer_r)
                 } else {
                     None
                 },
@@ -198,7 +211,7 @@ impl<const W: usize> Cache<W> {
             Side::Right => (
                 Side::Left,
                 if self.dirty.0 {
-                    Some(self.buffer_r.clone())
+                    Some(self.buffer_l)
                 } else {
                     None
                 },
@@ -1,20 +1,39 @@
 //! This module defines how cells and references to cells behave.
 
-use std::cell::RefCell;
-use std::rc::Rc;
+use crate::shared::{Counter, Shared};
 
-struct ConcreteCell<const W: usize> {
-    buffer: [u8; W],
+/// The symbols of a cell. The buffers are counted while they're alive, the ones shared by the
+/// cells of many tapes only once
+#[derive(Debug)]
+pub struct Buffer<const W: usize> {
+    symbols: [u8; W],
+    live: Counter,
+}
+
+impl<const W: usize> Buffer<W> {
+    fn shared(symbols: [u8; W], live: &Counter) -> Shared<Self> {
+        live.add();
+        Shared::new(Buffer {
+            symbols,
+            live: live.clone(),
+        })
+    }
+}
+
+impl<const W: usize> Drop for Buffer<W> {
+    fn drop(&mut self) {
+        self.live.sub();
+    }
 }
 
 #[derive(Debug)]
 pub enum Cell<const W: usize> {
     Full {
-        buffer: Rc<RefCell<[u8; W]>>,
+        buffer: Shared<Buffer<W>>,
         next: Link<W>,
     },
     Ghost {
-        buffer: Rc<RefCell<[u8; W]>>,
+        buffer: Shared<Buffer<W>>,
         next: Link<W>,
     },
     Empty {
@@ -27,9 +46,9 @@ pub enum Link<const W: usize> {
     /// No neighbor was created here yet
     Edge,
     /// A link to the same tape
-    Same(Rc<RefCell<Cell<W>>>),
+    Same(Shared<Cell<W>>),
     /// A link to a cell in a parent tape, will need to create a ghost when focusing
-    Uncle(Rc<RefCell<Cell<W>>>),
+    Uncle(Shared<Cell<W>>),
     /// No link
     None,
 }
@@ -44,22 +63,27 @@ impl<const W: usize> Link<W> {
         }
     }
 
+    /// The cell this link points to, if any
+    fn target(&self) -> Option<Shared<Cell<W>>> {
+        match self {
+            Link::Same(rc) | Link::Uncle(rc) => Some(rc.clone()),
+            Link::Edge | Link::None => None,
+        }
+    }
+
     fn focus(self) -> Cell<W> {
         match self {
             Link::Edge => Cell::Empty {
                 next: Link::<W>::Edge,
             },
-            Link::Same(rc) => {
-                let x = Rc::try_unwrap(rc).unwrap_or_else(|x| {
-                    panic!("This should've been the only Rc to the cell");
-                });
-                x.into_inner()
-            }
+            Link::Same(rc) => rc.try_unwrap().unwrap_or_else(|_| {
+                panic!("This should've been the only Rc to the cell");
+            }),
             Link::Uncle(rc) => {
                 // Can't take ownership because I'm in a children tape so I don't know how many
                 // references are pointing to the cell.
                 // I need to match
-                let x = match &*rc.borrow() {
+                let x = match &*rc.read() {
                     Cell::Full { buffer, next } => Cell::Ghost {
                         buffer: buffer.clone(),
                         next: next.to_uncle(),
@@ -91,6 +115,27 @@ impl<const W: usize> Cell<W> {
         new
     }
 
+    /// The contents of this cell followed by the contents of all the cells it links to, in
+    /// order. Cells which were never written to are filled with the empty symbol
+    pub fn buffers(&self, empty: u8) -> Vec<[u8; W]> {
+        let mut v = vec![self.read().unwrap_or([empty; W])];
+        let mut next = self.next().target();
+        while let Some(rc) = next {
+            let cell = rc.read();
+            v.push(cell.read().unwrap_or([empty; W]));
+            next = cell.next().target();
+        }
+        v
+    }
+
+    fn next(&self) -> &Link<W> {
+        match self {
+            Cell::Full { next, .. } => next,
+            Cell::Ghost { next, .. } => next,
+            Cell::Empty { next, .. } => next,
+        }
+    }
+
     /// Replace next with none and return the original
     fn extract_next(&mut self) -> Link<W> {
         let mut nxt = Link::None;
@@ -107,14 +152,11 @@ impl<const W: usize> Cell<W> {
     }
 
     fn set_next(&mut self, next: Link<W>) {
-        std::mem::replace(
-            match self {
-                Cell::Full { next, .. } => next,
-                Cell::Ghost { next, .. } => next,
-                Cell::Empty { next, .. } => next,
-            },
-            next,
-        );
+        *match self {
+            Cell::Full { next, .. } => next,
+            Cell::Ghost { next, .. } => next,
+            Cell::Empty { next, .. } => next,
+        } = next;
     }
 
     /// Opposite of focus, replaces self with the new cell and links the new head with the previous
@@ -122,36 +164,36 @@ impl<const W: usize> Cell<W> {
     pub fn shift(&mut self, mut new: Cell<W>) {
         std::mem::swap(self, &mut new);
         // Now self contains what was "new" before
-        let next_l = Link::Same(Rc::new(RefCell::new(new)));
+        let next_l = Link::Same(Shared::new(new));
         self.set_next(next_l);
     }
 
     /// Reads the content of the cell
     pub fn read(&self) -> Option<[u8; W]> {
         match self {
-            Cell::Full { buffer, .. } => Some(buffer.borrow().clone()),
-            Cell::Ghost { buffer, .. } => Some(buffer.borrow().clone()),
+            Cell::Full { buffer, .. } => Some(buffer.read().symbols),
+            Cell::Ghost { buffer, .. } => Some(buffer.read().symbols),
             Cell::Empty { .. } => None,
         }
     }
 
-    /// Write to the cell
-    pub fn write(&mut self, buff: [u8; W]) {
+    /// Write to the cell, a new buffer is counted in `live` if the cell had none of its own
+    pub fn write(&mut self, buff: [u8; W], live: &Counter) {
         match self {
-            Cell::Full { buffer, next } => {
-                buffer.borrow_mut().copy_from_slice(&buff);
+            Cell::Full { buffer, .. } => {
+                buffer.write().symbols.copy_from_slice(&buff);
             }
-            Cell::Ghost { buffer, next } => {
+            Cell::Ghost { next, .. } => {
                 let nxt = std::mem::replace(next, Link::None);
                 *self = Cell::Full {
-                    buffer: Rc::new(RefCell::new(buff)),
+                    buffer: Buffer::shared(buff, live),
                     next: nxt,
                 }
             }
             Cell::Empty { next } => {
                 let nxt = std::mem::replace(next, Link::None);
                 *self = Cell::Full {
-                    buffer: Rc::new(RefCell::new(buff)),
+                    buffer: Buffer::shared(buff, live),
                     next: nxt,
                 }
             }
@@ -192,11 +234,11 @@ impl<const W: usize> Cell<W> {
 }
 
 /// Create a full cell with the given content and given follower
-fn full_cell<const W: usize>(buffer: [u8; W], next: Option<Cell<W>>) -> Cell<W> {
+fn full_cell<const W: usize>(buffer: [u8; W], next: Option<Cell<W>>, live: &Counter) -> Cell<W> {
     Cell::Full {
-        buffer: Rc::new(RefCell::new(buffer)),
+        buffer: Buffer::shared(buffer, live),
         next: if let Some(x) = next {
-            Link::Same(Rc::new(RefCell::new(x)))
+            Link::Same(Shared::new(x))
         } else {
             Link::Edge
         },
@@ -208,24 +250,20 @@ pub fn empty_cell<const W: usize>() -> Cell<W> {
     Cell::Empty { next: Link::Edge }
 }
 
-/// Creates a chain of non empty cells using the given slice
-pub fn cells_from_slice<const W: usize>(buff: &[u8], empty: u8) -> Cell<W> {
-    dbg!(buff);
+/// Creates a chain of non empty cells using the given slice, counted in `live`
+pub fn cells_from_slice<const W: usize>(buff: &[u8], empty: u8, live: &Counter) -> Cell<W> {
     let mut buff_tmp = [empty; W];
     let (full_cells, last_rem) = (buff.len() / W, buff.len() % W);
 
     let rem_sl = &buff[(W * full_cells)..];
-    dbg!(rem_sl);
     buff_tmp[0..last_rem].copy_from_slice(rem_sl);
 
-    let mut head = full_cell(buff_tmp, None);
+    let mut head = full_cell(buff_tmp, None, live);
 
     for i in (0..full_cells).rev() {
-        dbg!(buff);
         let slice = &buff[(i * W)..((i + 1) * W)];
-        dbg!(slice);
         buff_tmp.copy_from_slice(slice);
-        head = full_cell(buff_tmp, Some(head));
+        head = full_cell(buff_tmp, Some(head), live);
     }
     head
 }
@@ -11,6 +11,7 @@ When a tape is created it will need an u8 value to treat as the empty symbol (th
 of the tape) and an array of u8 symbols to initialize the tape.
 */
 
+use crate::shared::Counter;
 use crate::tape::cache::{Cache, ShiftRet, Side};
 use crate::tape::cells::Cell;
 
@@ -24,6 +25,10 @@ pub struct Tape<const W: usize> {
     focus: Cell<W>,
     left: Cell<W>,
     right: Cell<W>,
+    /// The position of the head, 0 being the first symbol the tape was created with
+    position: isize,
+    /// How many cells holding symbols this tape and the ones split from the same tape hold
+    live: Counter,
 }
 
 impl<const W: usize> Tape<W> {
@@ -32,9 +37,10 @@ impl<const W: usize> Tape<W> {
     /// The resulting tape will be positioned so that the first read will return the first symbol in
     /// the slice
     pub fn create(empty: u8, init: &[u8]) -> Self {
-        let mut curr: Cell<W> = cells::cells_from_slice(init, empty);
+        let live = Counter::default();
+        let mut curr: Cell<W> = cells::cells_from_slice(init, empty, &live);
 
-        let mut head = curr.focus();
+        let head = curr.focus();
         let right = curr;
 
         let left = cells::empty_cell();
@@ -44,7 +50,7 @@ impl<const W: usize> Tape<W> {
             if let Some(x) = head.read() {
                 x
             } else {
-                empty_buff.clone()
+                empty_buff
             },
             empty_buff,
         );
@@ -55,9 +61,16 @@ impl<const W: usize> Tape<W> {
             left,
             right,
             focus: head,
+            position: 0,
+            live,
         }
     }
 
+    /// The symbol filling the tape outside of what was written
+    pub fn empty(&self) -> u8 {
+        self.empty
+    }
+
     /// Read the symbol under the cursor
     pub fn read(&self) -> u8 {
         self.cache.read()
@@ -69,8 +82,30 @@ impl<const W: usize> Tape<W> {
         self.cache.write(symb)
     }
 
+    /// The position of the head, 0 being the first symbol the tape was created with
+    pub fn position(&self) -> isize {
+        self.position
+    }
+
+    /// How many cells holding symbols are alive across this tape, the one it was created as and
+    /// all the tapes split from it. Tapes split from the same one share the cells neither of
+    /// them wrote to since, those are counted once
+    pub fn live_cells(&self) -> usize {
+        self.live.get()
+    }
+
+    /// The count behind `live_cells`, shared by all the tapes split from the same one
+    pub(crate) fn cell_counter(&self) -> Counter {
+        self.live.clone()
+    }
+
     /// Move the cursor in the given direction
     pub fn shift(&mut self, direction: Movement) {
+        match direction {
+            Movement::Left => self.position -= 1,
+            Movement::Right => self.position += 1,
+            Movement::Stay => {}
+        }
         let res = self.cache.shift(direction);
         match res {
             ShiftRet::Stay => {}
@@ -85,7 +120,7 @@ impl<const W: usize> Tape<W> {
                         };
                         let buff = self.cache.shift_flush(direction, &buff);
                         if let Some(x) = buff {
-                            self.right.write(x)
+                            self.right.write(x, &self.live)
                         }
                     }
                     Side::Right => {
@@ -96,7 +131,7 @@ impl<const W: usize> Tape<W> {
                         };
                         let buff = self.cache.shift_flush(direction, &buff);
                         if let Some(x) = buff {
-                            self.left.write(x)
+                            self.left.write(x, &self.live)
                         }
                     }
                 }
@@ -105,6 +140,57 @@ impl<const W: usize> Tape<W> {
         }
     }
 
+    /// Copy the region of the tape which contains non empty symbols, returns the copy and the
+    /// position of the head relative to its first symbol (negative if the head is to the left
+    /// of the region).
+    ///
+    /// If the tape only contains empty symbols the copy is empty and the position is 0
+    pub fn contents(&self) -> (Vec<u8>, isize) {
+        let mut left = self.left.buffers(self.empty);
+        let mut right = self.right.buffers(self.empty);
+        // The cells in the cache might not have been flushed yet
+        let (current, (side, other)) = self.cache.buffers();
+        match side {
+            Side::Left => left[0] = other,
+            Side::Right => right[0] = other,
+        }
+
+        let mut symbols = Vec::with_capacity((left.len() + right.len() + 1) * W);
+        for buff in left.iter().rev() {
+            symbols.extend_from_slice(buff);
+        }
+        symbols.extend_from_slice(&current);
+        for buff in right.iter() {
+            symbols.extend_from_slice(buff);
+        }
+        let head = (left.len() * W + self.cache.cursor()) as isize;
+
+        match symbols.iter().position(|&s| s != self.empty) {
+            None => (Vec::new(), 0),
+            Some(start) => {
+                let end = symbols.iter().rposition(|&s| s != self.empty).unwrap() + 1;
+                symbols.truncate(end);
+                symbols.drain(..start);
+                (symbols, head - start as isize)
+            }
+        }
+    }
+
+    /// Copy the `radius` symbols on each side of the head and the one under it, empty symbols
+    /// included
+    pub fn window(&self, radius: usize) -> Vec<u8> {
+        let (symbols, head) = self.contents();
+        let radius = radius as isize;
+        (head - radius..=head + radius)
+            .map(|i| {
+                usize::try_from(i)
+                    .ok()
+                    .and_then(|i| symbols.get(i).copied())
+                    .unwrap_or(self.empty)
+            })
+            .collect()
+    }
+
     fn tape_shift(&mut self, direction: Side) {
         match direction {
             Side::Left => {
@@ -123,26 +209,31 @@ impl<const W: usize> Tape<W> {
     /// Split the tape in `branches` independent copies
     pub fn split(mut self, branches: usize) -> Vec<Self> {
         if let Some(x) = self.cache.flush_current() {
-            self.focus.write(x);
+            self.focus.write(x, &self.live);
         }
 
         if let (side, Some(x)) = self.cache.flush_other() {
             match side {
                 Side::Left => {
-                    self.left.write(x);
+                    self.left.write(x, &self.live);
                 }
                 Side::Right => {
-                    self.right.write(x);
+                    self.right.write(x, &self.live);
                 }
             }
         }
 
+        // The copies share the cells just written instead of each writing its own
+        self.cache.mark_clean();
+
         let Tape {
             cache,
             empty,
             focus,
             left,
             right,
+            position,
+            live,
         } = self;
 
         let mut foc_refs = focus.make_refs(branches);
@@ -157,20 +248,37 @@ impl<const W: usize> Tape<W> {
             let left = left_refs.pop().unwrap();
 
             ret.push(Tape {
-                cache: cache.clone(),
+                cache,
                 empty,
                 focus,
                 right,
                 left,
+                position,
+                live: live.clone(),
             })
         }
         ret
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Movement {
+    #[cfg_attr(feature = "serde", serde(rename = "L"))]
     Left,
+    #[cfg_attr(feature = "serde", serde(rename = "R"))]
     Right,
+    #[cfg_attr(feature = "serde", serde(rename = "S"))]
     Stay,
 }
+
+impl Movement {
+    /// The letter standing for the movement in a description: `L`, `R` or `S`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Movement::Left => "L",
+            Movement::Right => "R",
+            Movement::Stay => "S",
+        }
+    }
+}
@@ -0,0 +1,998 @@
+use ndtm_rs::rules::{Output, Pattern, Write};
+use ndtm_rs::{
+    parse, Branch, Choice, Heuristic, Movement, Observer, ParseError, RuleStore, RunStats,
+    SearchStrategy, StepResult, Tape, Transition, Verdict, Warning, DTM, NDTM,
+};
+
+/// Guesses a position in the word and accepts if the symbols at it and the next one are equal
+const DOUBLE: &str = "
+tr
+0 a a R 0
+0 b b R 0
+0 a a R 1
+0 b b R 2
+1 a a S 3
+2 b b S 3
+acc
+3
+max
+100
+";
+
+fn only_as() -> RuleStore {
+    RuleStore::builder()
+        .rule(0, b'a', b'a', 0, Movement::Right)
+        .rule(0, b'_', b'_', 1, Movement::Stay)
+        .accept(1)
+        .build()
+}
+
+fn tape(word: &[u8]) -> Tape<3> {
+    Tape::create(b'_', word)
+}
+
+fn run(rules: &RuleStore, word: &[u8], max: usize, strategy: SearchStrategy) -> Verdict {
+    NDTM::builder(rules)
+        .max_steps(max)
+        .strategy(strategy)
+        .build(tape(word))
+        .run()
+}
+
+#[test]
+fn builder_accepts_and_rejects() {
+    let rules = only_as();
+    assert_eq!(
+        run(&rules, b"aaaa", 100, SearchStrategy::BestFirst),
+        Verdict::Accept
+    );
+    assert_eq!(
+        run(&rules, b"aaba", 100, SearchStrategy::BestFirst),
+        Verdict::Reject
+    );
+    assert_eq!(
+        run(&rules, b"", 100, SearchStrategy::BestFirst),
+        Verdict::Accept
+    );
+}
+
+#[test]
+fn step_limit_is_undecided() {
+    let rules = only_as();
+    assert_eq!(
+        run(&rules, b"aaaa", 3, SearchStrategy::BestFirst),
+        Verdict::Undecided
+    );
+}
+
+#[test]
+fn distances_prune_hopeless_branches() {
+    // State 4 can't reach the final state, so the branches moving there are never created
+    let desc = parse(
+        "tr\n0 a a R 0\n0 a a R 1\n0 a a R 4\n1 b b S 2\n4 a a R 4\n4 b b R 4\nacc\n2\n".as_bytes(),
+    )
+    .unwrap();
+    let rules = &desc.rules;
+    let dist: Vec<_> = [0, 1, 2, 4, 7].iter().map(|s| rules.distance(*s)).collect();
+    assert_eq!(dist, [Some(2), Some(1), Some(0), None, None]);
+
+    let mut machine = NDTM::builder(rules)
+        .max_steps(100)
+        .strategy(SearchStrategy::BreadthFirst)
+        .build(tape(b"aaab"));
+    let results = machine.fastforward(None);
+    assert!(matches!(results.last(), Some(StepResult::Success { .. })));
+    for r in &results {
+        if let StepResult::Split { new, .. } = r {
+            assert_eq!(new.len(), 2);
+        }
+    }
+    let stats = machine.stats();
+    assert!(stats.splits > 0);
+    assert_eq!(stats.created, 2 * stats.splits);
+
+    // Without a way to the final state from state 0 nothing is even started
+    let desc = parse("tr\n0 a a R 1\n1 a a L 0\n2 a a S 2\nacc\n2\n".as_bytes()).unwrap();
+    assert_eq!(desc.rules.distance(0), None);
+    let mut machine = NDTM::builder(&desc.rules).build(tape(b"aa"));
+    assert!(matches!(machine.step(), StepResult::FailAll));
+    assert_eq!(machine.run(), Verdict::Reject);
+    assert_eq!(machine.stats().steps, 0);
+}
+
+#[test]
+fn symbol_distances() {
+    // Reading `b` in state 0 accepts right away, reading `a` takes two transitions
+    let desc = parse("tr\n0 b b S 2\n0 a a R 1\n1 _ _ S 2\nacc\n2\n".as_bytes()).unwrap();
+    let rules = &desc.rules;
+    assert_eq!(rules.distance(0), Some(1));
+    assert_eq!(rules.symbol_distance(0, b'b'), Some(1));
+    assert_eq!(rules.symbol_distance(0, b'a'), Some(2));
+    assert_eq!(rules.symbol_distance(0, b'_'), None);
+    assert_eq!(rules.symbol_distance(1, b'_'), Some(1));
+    assert_eq!(rules.symbol_distance(1, b'a'), None);
+    assert_eq!(rules.symbol_distance(2, b'a'), Some(0));
+    assert_eq!(rules.estimate(Heuristic::State, 0, b'_'), Some(1));
+    assert_eq!(rules.estimate(Heuristic::Symbol, 0, b'a'), Some(2));
+
+    // Only the symbol aware estimate knows that nothing can be done reading `_` in state 0
+    for (heuristic, steps) in [(Heuristic::State, 1), (Heuristic::Symbol, 0)] {
+        let mut machine = NDTM::builder(rules).heuristic(heuristic).build(tape(b""));
+        assert_eq!(machine.run(), Verdict::Reject);
+        assert_eq!(machine.stats().steps, steps);
+    }
+}
+
+#[test]
+fn duplicate_branches_are_pruned() {
+    // The two branches created on the first `a` meet again in state 3 with the same tape
+    let desc = parse(
+        "tr\n0 a a R 1\n0 a a R 2\n1 b b R 3\n2 b b R 3\n3 b b R 3\n3 _ _ S 4\nacc\n4\n".as_bytes(),
+    )
+    .unwrap();
+    for (word, verdict) in [(&b"abbb"[..], Verdict::Accept), (b"abba", Verdict::Reject)] {
+        let machine = |dedup| {
+            NDTM::builder(&desc.rules)
+                .strategy(SearchStrategy::BreadthFirst)
+                .deduplicate(dedup)
+                .build(tape(word))
+        };
+        let mut plain = machine(false);
+        let plain_results = plain.fastforward(None);
+        assert_eq!(plain.pruned(), 0);
+        assert!(!plain_results
+            .iter()
+            .any(|r| matches!(r, StepResult::Pruned { .. })));
+
+        let mut dedup = machine(true);
+        let results = dedup.fastforward(None);
+        assert_eq!(dedup.pruned(), 1);
+        let pruned = results
+            .iter()
+            .filter(|r| matches!(r, StepResult::Pruned { .. }))
+            .count();
+        assert_eq!(pruned, 1);
+        assert!(results.len() < plain_results.len());
+        assert_eq!((dedup.run(), plain.run()), (verdict, verdict));
+    }
+}
+
+#[test]
+fn looping_branches_are_detected() {
+    // The branch staying in state 0 comes back to the same configuration forever, the other
+    // one fails on the second `a`
+    let desc = parse("tr\n0 a a S 0\n0 a a R 1\n1 b b S 2\nacc\n2\n".as_bytes()).unwrap();
+    let machine = |detect| {
+        NDTM::builder(&desc.rules)
+            .max_steps(50)
+            .strategy(SearchStrategy::BreadthFirst)
+            .detect_loops(detect)
+            .build(tape(b"aa"))
+    };
+    let mut plain = machine(false);
+    assert_eq!(plain.run(), Verdict::Undecided);
+
+    let mut detecting = machine(true);
+    let results = detecting.fastforward(None);
+    assert!(results.iter().any(|r| matches!(r, StepResult::Loop { .. })));
+    assert_eq!(detecting.run(), Verdict::Reject);
+    assert!(!detecting.some_undecided());
+
+    // A real way to the final state is still taken
+    let mut m = NDTM::builder(&desc.rules)
+        .max_steps(50)
+        .detect_loops(true)
+        .build(tape(b"ab"));
+    assert_eq!(m.run(), Verdict::Accept);
+}
+
+#[test]
+fn split_tapes_keep_the_cached_cells() {
+    // The head ends up on the first cell with the one to its left, just written to, still in
+    // the cache of the tape
+    let mut original = tape(b"abcdef");
+    original.shift(Movement::Left);
+    original.write(b'x');
+    original.shift(Movement::Right);
+    for mut copy in original.split(2) {
+        assert_eq!(copy.contents(), (b"xabcdef".to_vec(), 1));
+        // Moving far enough for the cell to leave the cache
+        for _ in 0..4 {
+            copy.shift(Movement::Right);
+        }
+        assert_eq!(copy.contents(), (b"xabcdef".to_vec(), 5));
+        for _ in 0..5 {
+            copy.shift(Movement::Left);
+        }
+        assert_eq!(copy.read(), b'x');
+    }
+}
+
+#[test]
+fn strategies_agree() {
+    let desc = parse(DOUBLE.as_bytes()).unwrap();
+    for strategy in [
+        SearchStrategy::BestFirst,
+        SearchStrategy::BreadthFirst,
+        SearchStrategy::DepthFirst,
+        SearchStrategy::IterativeDeepening(2),
+    ] {
+        assert_eq!(run(&desc.rules, b"abab", 100, strategy), Verdict::Reject);
+        assert_eq!(run(&desc.rules, b"abaab", 100, strategy), Verdict::Accept);
+        assert_eq!(run(&desc.rules, b"babba", 100, strategy), Verdict::Accept);
+    }
+}
+
+#[test]
+fn iterative_deepening_restarts() {
+    // One branch runs right forever, the other accepts after ten steps
+    let rules = RuleStore::builder()
+        .rule(0, b'a', b'a', 1, Movement::Right)
+        .rule(0, b'a', b'a', 2, Movement::Stay)
+        .rule(1, b'_', b'_', 1, Movement::Right)
+        .rule(1, b'b', b'b', 3, Movement::Stay)
+        .rule(2, b'a', b'a', 4, Movement::Stay)
+        .rule(4, b'a', b'b', 4, Movement::Right)
+        .rule(4, b'_', b'_', 3, Movement::Stay)
+        .accept(3)
+        .build();
+    for dedup in [false, true] {
+        let mut machine = NDTM::builder(&rules)
+            .max_steps(1000)
+            .strategy(SearchStrategy::IterativeDeepening(2))
+            .deduplicate(dedup)
+            .record_witness(true)
+            .build(tape(b"aaaaaaa"));
+        assert_eq!(machine.run(), Verdict::Accept);
+        assert_eq!(machine.witness().unwrap().len(), 10);
+        // Far fewer steps than the looping branch would take before the step limit, but the
+        // first ones are taken again at every restart
+        let steps = machine.stats().steps;
+        assert!(steps > 20 && steps < 100, "{steps}");
+    }
+
+    // The search ends once the limit goes past the step limit
+    let rules = RuleStore::builder()
+        .rule(0, b'_', b'_', 0, Movement::Right)
+        .rule(0, b'b', b'b', 1, Movement::Stay)
+        .accept(1)
+        .build();
+    let strategy = SearchStrategy::IterativeDeepening(3);
+    assert_eq!(run(&rules, b"", 20, strategy), Verdict::Undecided);
+}
+
+#[test]
+fn fastforward_ends_with_verdict() {
+    let desc = parse(DOUBLE.as_bytes()).unwrap();
+    let mut machine = NDTM::builder(&desc.rules)
+        .max_steps(100)
+        .heuristic(Heuristic::Symbol)
+        .build(tape(b"abbab"));
+    let res = machine.fastforward(None);
+    assert!(matches!(res.last(), Some(StepResult::Success { .. })));
+    assert!(res.iter().any(|r| matches!(r, StepResult::Split { .. })));
+
+    let mut machine = NDTM::builder(&desc.rules)
+        .max_steps(100)
+        .heuristic(Heuristic::Symbol)
+        .build(tape(b"abbab"));
+    assert_eq!(machine.fastforward(Some(2)).len(), 2);
+}
+
+#[test]
+fn iterate_and_run_with_budget() {
+    let desc = parse(DOUBLE.as_bytes()).unwrap();
+    let machine = |word: &[u8]| {
+        NDTM::builder(&desc.rules)
+            .max_steps(desc.max_steps)
+            .strategy(SearchStrategy::BreadthFirst)
+            .build(tape(word))
+    };
+
+    // The iterator ends with the result deciding the computation
+    let results: Vec<StepResult> = machine(b"abaab").collect();
+    assert!(matches!(results.last(), Some(StepResult::Success { .. })));
+    let results: Vec<StepResult> = machine(b"abab").collect();
+    assert!(matches!(results.last(), Some(StepResult::FailAll)));
+    let total = results.len();
+
+    // A computation cut by the budget can be resumed where it stopped
+    let mut m = machine(b"abab");
+    let first = m.run_until(3);
+    assert_eq!(
+        (first.verdict, first.finished, first.steps),
+        (Verdict::Undecided, false, 3)
+    );
+    let rest = m.run_until(usize::MAX);
+    assert_eq!((rest.verdict, rest.finished), (Verdict::Reject, true));
+    assert_eq!(first.steps + rest.steps, total);
+    assert!(rest.branches > 1);
+    assert!(m.next().is_none());
+    assert_eq!(m.run_until(10).steps, 0);
+}
+
+#[test]
+fn verdict_is_kept_by_every_runner() {
+    let desc = parse(DOUBLE.as_bytes()).unwrap();
+    let mut m = NDTM::builder(&desc.rules)
+        .max_steps(desc.max_steps)
+        .strategy(SearchStrategy::BreadthFirst)
+        .build(tape(b"abaab"));
+    let first = m.run_until(3);
+    assert!(!first.finished);
+    assert_eq!(m.run(), Verdict::Accept);
+    let accepted = m.accepted_tape().unwrap().contents();
+    let steps = m.stats().steps;
+
+    // Nothing is advanced once the computation ended
+    let summary = m.run_until(10);
+    assert_eq!(
+        (summary.verdict, summary.finished, summary.steps),
+        (Verdict::Accept, true, 0)
+    );
+    assert!(m.next().is_none());
+    assert!(matches!(m.step(), StepResult::Success { .. }));
+    assert!(m.fastforward(None).is_empty());
+    assert_eq!(m.run(), Verdict::Accept);
+    assert_eq!(m.accepted_tape().unwrap().contents(), accepted);
+    assert_eq!(m.stats().steps, steps);
+
+    let mut m = NDTM::builder(&desc.rules)
+        .max_steps(desc.max_steps)
+        .build(tape(b"abab"));
+    assert!(m.by_ref().last().is_some());
+    assert_eq!(m.run(), Verdict::Reject);
+    assert!(matches!(m.step(), StepResult::FailAll));
+    assert_eq!(m.run_until(10).verdict, Verdict::Reject);
+}
+
+#[test]
+fn witness_and_tape_of_accepting_branch() {
+    // Replaces every `a` with `b` and accepts at the end of the word
+    let rules = RuleStore::builder()
+        .rule(0, b'a', b'b', 0, Movement::Right)
+        .rule(0, b'b', b'b', 0, Movement::Right)
+        .rule(0, b'_', b'_', 1, Movement::Left)
+        .accept(1)
+        .build();
+    // The transitions are only kept when asked for
+    let mut machine = NDTM::builder(&rules).max_steps(100).build(tape(b"aba"));
+    assert_eq!(machine.run(), Verdict::Accept);
+    assert!(machine.witness().is_none());
+
+    let mut machine = NDTM::builder(&rules)
+        .max_steps(100)
+        .strategy(SearchStrategy::DepthFirst)
+        .record_witness(true)
+        .build(tape(b"aba"));
+    assert_eq!(machine.run(), Verdict::Accept);
+
+    let witness = machine.witness().unwrap();
+    let read: Vec<u8> = witness.iter().map(|s| s.read).collect();
+    assert_eq!(read, b"aba_");
+    assert_eq!(witness.last().unwrap().transition.state, 1);
+
+    let (contents, head) = machine.accepted_tape().unwrap().contents();
+    assert_eq!(contents, b"bbb");
+    assert_eq!(head, 2);
+}
+
+#[test]
+fn description_sections() {
+    let desc = parse(
+        "# comment\n\ntr\n0 x x R 0 # skip\n0 # # S 1\nacc\n1\nrej\n2\nblank\n.\nmax\n7\nrun\ntr\nacc\n"
+            .as_bytes(),
+    )
+    .unwrap();
+    assert_eq!(desc.max_steps, 7);
+    assert_eq!(desc.blank, b'.');
+    assert!(desc.rules.is_reject(2));
+    assert_eq!(desc.inputs, vec![b"tr".to_vec(), b"acc".to_vec()]);
+}
+
+#[test]
+fn parse_error_position() {
+    let err: ParseError = match parse("tr\n0 a a R 1\n0 b b X 1\n".as_bytes()) {
+        Ok(_) => panic!("The direction should be rejected"),
+        Err(e) => e,
+    };
+    assert_eq!((err.line, err.column), (3, 7));
+    assert_eq!(err.kind.expected(), "a direction (L, R or S)");
+}
+
+#[test]
+fn named_states_and_symbols() {
+    let desc = parse(
+        "tr\nscan empty empty S done\nscan a a R scan\nacc\ndone\nblank\nempty\nmax\n10\nrun\na a\n"
+            .as_bytes(),
+    )
+    .unwrap();
+    let names = &desc.names;
+    assert_eq!(names.state("scan"), Some(0));
+    assert_eq!(names.state_name(1), "done");
+    let blank = names.symbol("empty").unwrap();
+    assert_eq!(desc.blank, blank);
+    assert_eq!(names.word(&[b'a', blank]), "a empty");
+    assert_eq!(desc.inputs, vec![b"aa".to_vec()]);
+
+    let mut machine = NDTM::builder(&desc.rules)
+        .max_steps(desc.max_steps)
+        .build(Tape::<2>::create(desc.blank, &desc.inputs[0]));
+    assert_eq!(machine.run(), Verdict::Accept);
+}
+
+#[test]
+fn hash_is_a_symbol() {
+    let desc = parse(
+        "tr\nscan a # R scan # marks the a\nscan # mark R scan\nscan # # R sink\n\
+         scan _ _ S done\nsink _ _ S done\nacc\ndone\nrun\na#\n"
+            .as_bytes(),
+    )
+    .unwrap();
+    let names = &desc.names;
+    let scan = names.state("scan").unwrap();
+    let mark = names.symbol("mark").unwrap();
+    assert_eq!(desc.rules.transitions(scan, b'a')[0].symb, b'#');
+    let writes: Vec<u8> = desc
+        .rules
+        .transitions(scan, b'#')
+        .iter()
+        .map(|t| t.symb)
+        .collect();
+    assert_eq!(writes, [mark, b'#']);
+    assert_eq!(desc.inputs, vec![b"a#".to_vec()]);
+    assert_eq!(
+        run(&desc.rules, &desc.inputs[0], 10, SearchStrategy::BestFirst),
+        Verdict::Accept
+    );
+}
+
+#[test]
+fn start_state_conflict() {
+    let err = parse("tr\n0 a a R q\nstart\nq\n".as_bytes()).err().unwrap();
+    assert_eq!((err.line, err.column), (4, 1));
+}
+
+#[test]
+fn section_names_are_reserved() {
+    let err = parse("tr\nq0 a a R run\nacc\nrun\nmax\n10\n".as_bytes())
+        .err()
+        .unwrap();
+    assert_eq!((err.line, err.column), (2, 10));
+    assert_eq!(
+        err.to_string(),
+        "2:10: expected a name other than the one of a section, found `run`"
+    );
+    let err = parse("tr\n0 [a,max] a R 1\nacc\n1\n".as_bytes())
+        .err()
+        .unwrap();
+    assert_eq!((err.line, err.column), (2, 3));
+}
+
+#[test]
+fn dot_export() {
+    let desc =
+        parse("tr\nq0 a b R q0\nq0 a a R done\nq0 _ _ S done\nacc\ndone\n".as_bytes()).unwrap();
+    let dot = desc.rules.to_dot(&desc.names);
+    let done = desc.names.state("done").unwrap();
+    assert!(dot.starts_with("digraph {"));
+    assert!(dot.contains(&format!("{} [label=\"done\", shape=doublecircle];", done)));
+    assert!(dot.contains("0 [label=\"q0\", shape=circle];"));
+    // Both transitions reading `a` are alternatives of the same choice
+    assert!(dot.contains("0 -> 0 [label=\"a/b,R\", color=red"));
+    assert!(dot.contains(&format!(
+        "0 -> {} [label=\"_/_,S\\na/a,R\", color=red",
+        done
+    )));
+}
+
+#[test]
+fn analysis_warnings() {
+    let rules = RuleStore::builder()
+        .rule(0, b'a', b'x', 1, Movement::Right)
+        .rule(0, b'_', b'_', 2, Movement::Stay)
+        .rule(2, b'_', b'_', 0, Movement::Stay)
+        .rule(3, b'a', b'a', 2, Movement::Stay)
+        .accept(2)
+        .build();
+    assert_eq!(
+        rules.analyze(),
+        vec![
+            Warning::DeadEnd(1),
+            Warning::Unreachable(3),
+            Warning::FinalWithTransitions(2),
+            Warning::NeverRead(b'x'),
+        ]
+    );
+    assert!(only_as().analyze().is_empty());
+}
+
+#[test]
+fn determinism_report() {
+    let desc = parse(DOUBLE.as_bytes()).unwrap();
+    let report = desc.rules.determinism();
+    assert!(!report.deterministic);
+    assert!(!desc.rules.is_deterministic());
+    assert_eq!(
+        report.choices,
+        vec![
+            Choice {
+                state: 0,
+                symbols: vec![b'a'],
+                fan_out: 2
+            },
+            Choice {
+                state: 0,
+                symbols: vec![b'b'],
+                fan_out: 2
+            },
+        ]
+    );
+    assert_eq!(report.max_branching(), 2);
+
+    let report = only_as().determinism();
+    assert!(report.deterministic && report.choices.is_empty());
+    assert_eq!(report.max_branching(), 1);
+}
+
+#[test]
+fn deterministic_runner_agrees() {
+    let rules = only_as();
+    for (word, max) in [
+        (&b"aaaa"[..], 100),
+        (b"aaba", 100),
+        (b"", 100),
+        (b"aaaa", 3),
+    ] {
+        let mut machine = DTM::new(tape(word), &rules, max).unwrap();
+        assert_eq!(
+            machine.run(),
+            run(&rules, word, max, SearchStrategy::BestFirst)
+        );
+    }
+    let desc = parse(DOUBLE.as_bytes()).unwrap();
+    assert!(DTM::new(tape(b"ab"), &desc.rules, 100).is_none());
+}
+
+#[test]
+fn compiled_transitions_match_rules() {
+    let mut rules = parse(DOUBLE.as_bytes()).unwrap().rules;
+    for state in 0..5 {
+        for symb in [b'a', b'b', b'_'] {
+            let expected = match rules.get(state, symb) {
+                Output::None => vec![],
+                Output::Simple(tr) => vec![tr],
+                Output::Multi(trs) => trs,
+            };
+            assert_eq!(rules.transitions(state, symb), expected.as_slice());
+        }
+    }
+    // The table is compiled again after the rules change
+    rules.add_rule(7, b'a', b'b', 3, Movement::Left);
+    assert_eq!(rules.transitions(7, b'a').len(), 1);
+    assert!(rules.transitions(usize::MAX, b'a').is_empty());
+}
+
+#[test]
+fn large_state_ids() {
+    let desc = parse("tr\n5000000 _ _ S 1\n0 a a R 5000000\nacc\n1\n".as_bytes()).unwrap();
+    assert_eq!(desc.rules.transitions(5_000_000, b'_')[0].state, 1);
+    assert!(desc.rules.transitions(4_999_999, b'_').is_empty());
+    assert!(desc.rules.transitions(5_000_000, b'a').is_empty());
+    assert_eq!(
+        run(&desc.rules, b"a", 10, SearchStrategy::BestFirst),
+        Verdict::Accept
+    );
+}
+
+#[test]
+fn reject_states() {
+    // Either rejects right away reading `b`, or keeps going and accepts at the end of the word
+    let rules = RuleStore::builder()
+        .rule(0, b'a', b'a', 0, Movement::Right)
+        .rule(0, b'b', b'b', 0, Movement::Right)
+        .rule(0, b'b', b'b', 2, Movement::Stay)
+        .rule(0, b'_', b'_', 1, Movement::Stay)
+        .accept(1)
+        .reject(2)
+        .build();
+    let machine = |word: &[u8], abort: bool| {
+        let mut m = NDTM::builder(&rules)
+            .max_steps(100)
+            .abort_on_reject(abort)
+            .build(tape(word));
+        let verdict = m.run();
+        (verdict, m.some_rejected())
+    };
+    assert_eq!(machine(b"ab", false), (Verdict::Accept, true));
+    assert_eq!(machine(b"ab", true), (Verdict::Reject, true));
+    assert_eq!(machine(b"aa", true), (Verdict::Accept, false));
+
+    // The rejection is explicit only if a reject state is entered
+    let rules = parse("tr\n0 a a R 0\n0 b b S 1\n0 _ _ S 2\nrej\n1\nacc\n2\nmax\n10\n".as_bytes())
+        .unwrap()
+        .rules;
+    for (word, rejected) in [(&b"ab"[..], true), (b"ac", false)] {
+        let mut m = NDTM::builder(&rules).max_steps(100).build(tape(word));
+        assert_eq!(m.run(), Verdict::Reject);
+        assert_eq!(m.some_rejected(), rejected);
+    }
+}
+
+#[test]
+fn states_leading_to_reject_abort() {
+    // State 3 can't reach the final state, only the reject state
+    let rules = RuleStore::builder()
+        .rule(0, b'a', b'a', 3, Movement::Right)
+        .rule(0, b'a', b'a', 1, Movement::Right)
+        .rule(3, b'b', b'b', 2, Movement::Right)
+        .rule(1, b'b', b'b', 5, Movement::Right)
+        .rule(5, b'_', b'_', 4, Movement::Right)
+        .accept(4)
+        .reject(2)
+        .build();
+    assert_eq!(rules.distance(3), None);
+    assert_eq!(rules.reject_distance(3), Some(1));
+    for strategy in [
+        SearchStrategy::BreadthFirst,
+        SearchStrategy::DepthFirst,
+        SearchStrategy::BestFirst,
+    ] {
+        let machine = |abort| {
+            NDTM::builder(&rules)
+                .strategy(strategy)
+                .abort_on_reject(abort)
+                .build(tape(b"ab"))
+        };
+        let mut m = machine(true);
+        assert_eq!(m.run(), Verdict::Reject, "{:?}", strategy);
+        assert!(m.some_rejected());
+        // Without aborting the branch is still pruned as hopeless
+        let mut m = machine(false);
+        assert_eq!(m.run(), Verdict::Accept, "{:?}", strategy);
+        assert!(!m.some_rejected());
+    }
+
+    // Same from the initial state
+    let rules = parse("tr\n0 a a R 1\n1 a a S 2\n3 a a S 4\nrej\n2\nacc\n4\n".as_bytes())
+        .unwrap()
+        .rules;
+    for abort in [false, true] {
+        let mut m = NDTM::builder(&rules)
+            .abort_on_reject(abort)
+            .build(tape(b"aa"));
+        assert_eq!(m.run(), Verdict::Reject);
+        assert_eq!(m.some_rejected(), abort);
+    }
+}
+
+#[test]
+fn wildcards_and_classes() {
+    // Skips anything until the first `c`, which must be followed by `a` or `b`
+    let desc = parse(
+        "tr\n0 * * R 0\n0 c c R 1\n0 _ _ S 3\n1 [ab] x S 2\nacc\n2\nrej\n3\nmax\n100\n".as_bytes(),
+    )
+    .unwrap();
+    let rules = &desc.rules;
+    assert_eq!(
+        rules.transitions(0, b'z'),
+        &[Transition {
+            state: 0,
+            symb: b'z',
+            dir: Movement::Right
+        }]
+    );
+    // Specific rules override the wildcard
+    assert_eq!(rules.transitions(0, b'c')[0].state, 1);
+    assert_eq!(rules.transitions(0, b'_')[0].state, 3);
+    assert_eq!(rules.transitions(1, b'b')[0].symb, b'x');
+    assert!(rules.transitions(1, b'c').is_empty());
+    assert!(rules.is_deterministic());
+    for (word, verdict) in [
+        (&b"zzca"[..], Verdict::Accept),
+        (b"zcb", Verdict::Accept),
+        (b"zcz", Verdict::Reject),
+        (b"zzz", Verdict::Reject),
+    ] {
+        assert_eq!(run(rules, word, 100, SearchStrategy::BestFirst), verdict);
+    }
+
+    // Classes override the rules for any symbol, overlapping classes are alternatives
+    let rules = RuleStore::builder()
+        .pattern(0, Pattern::Any, Write::Same, 1, Movement::Stay)
+        .pattern(
+            0,
+            Pattern::Class([b'a', b'b'].into()),
+            Write::Symbol(b'x'),
+            2,
+            Movement::Stay,
+        )
+        .pattern(
+            0,
+            Pattern::Class([b'b'].into()),
+            Write::Same,
+            3,
+            Movement::Stay,
+        )
+        .build();
+    assert_eq!(rules.transitions(0, b'c')[0].state, 1);
+    assert_eq!(rules.transitions(0, b'a').len(), 1);
+    assert_eq!(rules.transitions(0, b'b').len(), 2);
+    assert!(!rules.is_deterministic());
+}
+
+/// Copies the input on the second tape, then checks it's a palindrome reading the copy backwards
+const PALINDROME: &str = "
+tapes
+2
+tr
+0 a _ a a R R 0
+0 b _ b b R R 0
+0 _ _ _ _ L L back
+back a a a a L S back
+back a b a b L S back
+back b a b a L S back
+back b b b b L S back
+back _ a _ a R S cmp
+back _ b _ b R S cmp
+cmp a a a a R L cmp
+cmp b b b b R L cmp
+cmp _ _ _ _ S S done
+acc
+done
+max
+100
+";
+
+#[test]
+fn multi_tape_machines() {
+    let desc = parse(PALINDROME.as_bytes()).unwrap();
+    assert_eq!(desc.rules.tapes(), 2);
+    for (word, verdict) in [
+        (&b"abba"[..], Verdict::Accept),
+        (b"aba", Verdict::Accept),
+        (b"abb", Verdict::Reject),
+    ] {
+        assert_eq!(
+            run(&desc.rules, word, 100, SearchStrategy::BestFirst),
+            verdict
+        );
+    }
+
+    // Guesses which tape holds a `b`, splitting both tapes
+    let rules = RuleStore::builder()
+        .tapes(2)
+        .multi_rule(0, b"ab", b"ab", 0, &[Movement::Stay, Movement::Right])
+        .multi_rule(0, b"ab", b"ab", 1, &[Movement::Right, Movement::Stay])
+        .multi_rule(0, b"b_", b"b_", 2, &[Movement::Stay, Movement::Stay])
+        .multi_rule(1, b"bb", b"bb", 2, &[Movement::Stay, Movement::Stay])
+        .accept(2)
+        .build();
+    assert!(!rules.is_deterministic());
+    let mut machine = NDTM::builder(&rules)
+        .max_steps(10)
+        .strategy(SearchStrategy::BreadthFirst)
+        .record_witness(true)
+        .build_with_tapes(vec![tape(b"ab"), tape(b"b")]);
+    assert_eq!(machine.run(), Verdict::Accept);
+    let witness = machine.witness().unwrap();
+    assert_eq!(witness[0].others[0].read, b'b');
+    let tapes = machine.accepted_tapes().unwrap();
+    assert_eq!(tapes.len(), 2);
+    assert_eq!(tapes[0].contents(), (b"ab".to_vec(), 1));
+    assert_eq!(tapes[1].contents(), (b"b".to_vec(), 0));
+}
+
+#[test]
+fn multi_tape_parse_errors() {
+    let err = parse("tapes\n2\ntr\n0 * a a a R R 1\n".as_bytes())
+        .err()
+        .unwrap();
+    assert_eq!((err.line, err.column), (4, 3));
+    let err = parse("tapes\n2\ntr\n0 a a a R R 1\n".as_bytes())
+        .err()
+        .unwrap();
+    assert_eq!((err.line, err.column), (4, 13));
+}
+
+#[test]
+fn trace_of_the_steps() {
+    let desc = parse(DOUBLE.as_bytes()).unwrap();
+    let mut machine = NDTM::builder(&desc.rules)
+        .max_steps(desc.max_steps)
+        .strategy(SearchStrategy::DepthFirst)
+        .build(tape(b"abb"));
+    machine.trace(Some(1));
+    assert_eq!(machine.run(), Verdict::Accept);
+    let trace = machine.take_trace();
+    assert!(machine.take_trace().is_empty());
+
+    // The last event is the accepting step, in the middle of the `bb` pair
+    let last = trace.last().unwrap();
+    assert_eq!(last.head, 2);
+    assert_eq!(last.window, b"bb_");
+    assert_eq!((last.step.state, last.step.read), (2, b'b'));
+    assert_eq!(last.step.transition.state, 3);
+
+    // Only the initial machine has no parent, and the symbol read is the one in the window middle
+    let first = &trace[0];
+    assert_eq!((first.machine, first.parent), (2, Some(0)));
+    assert_eq!(first.window, b"_ab");
+    for event in &trace {
+        assert_eq!(event.parent.is_none(), event.machine == 0);
+        assert_eq!(event.window[1], event.step.read);
+    }
+
+    // Nothing is recorded unless asked for
+    let mut machine = NDTM::builder(&desc.rules)
+        .max_steps(desc.max_steps)
+        .strategy(SearchStrategy::DepthFirst)
+        .build(tape(b"abb"));
+    machine.run();
+    assert!(machine.take_trace().is_empty());
+}
+
+#[test]
+fn observer_events() {
+    #[derive(Default)]
+    struct Counter {
+        stepped: usize,
+        created: Vec<(usize, usize)>,
+        failed: Vec<usize>,
+        accepted: Option<(usize, usize, Vec<u8>)>,
+        exhausted: bool,
+    }
+
+    impl<const W: usize> Observer<W> for Counter {
+        fn stepped(&mut self, _: Branch<'_, W>) {
+            self.stepped += 1;
+        }
+
+        fn created(&mut self, source: usize, branch: Branch<'_, W>) {
+            self.created.push((source, branch.id()));
+        }
+
+        fn failed(&mut self, branch: Branch<'_, W>) {
+            self.failed.push(branch.id());
+        }
+
+        fn accepted(&mut self, branch: Branch<'_, W>) {
+            let (tape, _) = branch.tape().contents();
+            self.accepted = Some((branch.id(), branch.state(), tape));
+        }
+
+        fn exhausted(&mut self) {
+            self.exhausted = true;
+        }
+    }
+
+    let desc = parse(DOUBLE.as_bytes()).unwrap();
+    let machine = |word: &[u8], counter: &mut Counter| {
+        NDTM::builder(&desc.rules)
+            .max_steps(desc.max_steps)
+            .strategy(SearchStrategy::BreadthFirst)
+            .build(tape(word))
+            .with_observer(counter)
+            .run()
+    };
+
+    let mut counter = Counter::default();
+    assert_eq!(machine(b"abb", &mut counter), Verdict::Accept);
+    assert_eq!(counter.created[..2], [(0, 1), (0, 2)]);
+    let (_, state, tape) = counter.accepted.unwrap();
+    assert_eq!((state, tape), (3, b"abb".to_vec()));
+    assert!(!counter.exhausted);
+
+    let mut counter = Counter::default();
+    assert_eq!(machine(b"ab", &mut counter), Verdict::Reject);
+    assert!(counter.accepted.is_none() && counter.exhausted);
+    // Both guesses fail, the machine continuing past the first one splits again
+    assert_eq!(counter.created, [(0, 1), (0, 2), (2, 3), (2, 4)]);
+    assert_eq!(counter.failed, [1, 3, 4]);
+    assert!(counter.stepped > 0);
+}
+
+#[test]
+fn run_statistics() {
+    let desc = parse(DOUBLE.as_bytes()).unwrap();
+    let mut machine = NDTM::builder(&desc.rules)
+        .max_steps(desc.max_steps)
+        .strategy(SearchStrategy::BreadthFirst)
+        .build(tape(b"abab"));
+    assert_eq!(machine.stats(), RunStats::default());
+    let results: Vec<StepResult> = machine.by_ref().collect();
+    let stats = machine.stats();
+
+    // The last result, FailAll, isn't a step of any machine
+    assert_eq!(stats.steps, results.len() - 1);
+    let count = |f: fn(&StepResult) -> bool| results.iter().filter(|r| f(r)).count();
+    // The first step of a branch created by a split applies the transition it was given, which
+    // isn't the only one available
+    let stepped = count(|r| matches!(r, StepResult::DetStep { .. }));
+    assert!(stats.det_steps < stepped && stepped <= stats.det_steps + stats.created);
+    // Without choices every step but the accepting one is counted
+    let rules = RuleStore::builder()
+        .rule(0, b'a', b'a', 0, Movement::Right)
+        .rule(0, b'_', b'_', 1, Movement::Stay)
+        .accept(1)
+        .build();
+    let mut det = NDTM::builder(&rules).build(tape(b"aaa"));
+    assert_eq!(det.run(), Verdict::Accept);
+    assert_eq!((det.stats().steps, det.stats().det_steps), (4, 3));
+    assert_eq!(
+        stats.splits,
+        count(|r| matches!(r, StepResult::Split { .. }))
+    );
+    assert_eq!(
+        stats.failed,
+        count(|r| matches!(r, StepResult::BranchFail { .. }))
+    );
+    assert_eq!(stats.undecided, 0);
+    // Every split into two adds one branch, and they all fail in the end
+    assert_eq!(stats.created, 2 * stats.splits);
+    assert_eq!(stats.failed, stats.created - stats.splits + 1);
+    assert!(stats.peak_frontier >= 2 && stats.peak_cells >= 2);
+
+    // The time stops with the computation
+    assert_eq!(machine.stats().elapsed, stats.elapsed);
+
+    // Only the cells of the computation itself are counted
+    let other = tape(&[b'a'; 299]);
+    assert_eq!(other.live_cells(), 100);
+    let mut again = NDTM::builder(&desc.rules)
+        .max_steps(desc.max_steps)
+        .strategy(SearchStrategy::BreadthFirst)
+        .build(tape(b"abab"));
+    again.run();
+    assert_eq!(again.stats().peak_cells, stats.peak_cells);
+    // Split tapes share the cells they didn't write to
+    let copies = other.split(3);
+    assert!(copies.iter().all(|t| t.live_cells() == 100));
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_runner_agrees() {
+    // Rewrites every symbol of the word either way, so the frontier grows past a batch, and
+    // accepts if the last one written is `b` and the word had more than one symbol, rejects if
+    // it's `a`
+    let desc = parse(
+        "tr\n0 a a R 0\n0 a b R 0\n0 b b R 0\n0 b a R 0\n0 _ _ L 1\n1 b b L 2\n1 a a S 4\n\
+         2 a a S 3\n2 b b S 3\nacc\n3\nrej\n4\nmax\n100\n"
+            .as_bytes(),
+    )
+    .unwrap();
+    for (word, max) in [
+        (&b"abbabaab"[..], 100),
+        (b"b", 100),
+        (b"", 100),
+        (b"abbabaab", 5),
+    ] {
+        for abort in [false, true] {
+            let machine = || {
+                NDTM::builder(&desc.rules)
+                    .max_steps(max)
+                    .abort_on_reject(abort)
+                    .build(tape(word))
+            };
+            let verdict = machine().run();
+            let mut par = machine();
+            let par_verdict = par.par_run(1);
+            assert_eq!(par.some_rejected(), !word.is_empty() && max == 100);
+            // When aborting, the first branch to end decides, and the parallel runner doesn't
+            // advance them in the same order. It doesn't depend on the number of threads though
+            if !abort || word.len() < 2 {
+                assert_eq!(par_verdict, verdict);
+            }
+            for threads in [1, 2, 4] {
+                assert_eq!(machine().par_run(threads), par_verdict);
+                let results = machine().par_fastforward(threads);
+                let last = match par_verdict {
+                    Verdict::Accept => matches!(results.last(), Some(StepResult::Success { .. })),
+                    _ => matches!(results.last(), Some(StepResult::FailAll)),
+                };
+                assert!(last, "{:?} with {} threads", word, threads);
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+#![cfg(feature = "serde")]
+
+use ndtm_rs::parser::{from_json, from_toml, to_json, to_toml};
+use ndtm_rs::{parse, Tape, Verdict, NDTM};
+
+const DESCRIPTION: &str = "
+tr
+0 a a R 0
+0 a b R 1
+1 _ _ S 2
+acc
+2
+rej
+3
+blank
+_
+max
+20
+run
+aa
+ab
+";
+
+fn verdicts(desc: &ndtm_rs::Description) -> Vec<Verdict> {
+    desc.inputs
+        .iter()
+        .map(|w| {
+            NDTM::builder(&desc.rules)
+                .max_steps(desc.max_steps)
+                .build(Tape::<2>::create(desc.blank, w))
+                .run()
+        })
+        .collect()
+}
+
+#[test]
+fn json_round_trip() {
+    let desc = parse(DESCRIPTION.as_bytes()).unwrap();
+    let json = to_json(&desc);
+    let back = from_json(&json).unwrap();
+    assert_eq!(to_json(&back), json);
+    assert_eq!(verdicts(&back), vec![Verdict::Accept, Verdict::Reject]);
+}
+
+#[test]
+fn toml_round_trip() {
+    let desc = parse(DESCRIPTION.as_bytes()).unwrap();
+    let toml = to_toml(&desc);
+    let back = from_toml(&toml).unwrap();
+    assert_eq!(to_toml(&back), toml);
+    assert!(back.rules.is_reject(3));
+    assert_eq!(verdicts(&back), vec![Verdict::Accept, Verdict::Reject]);
+}
+
+#[test]
+fn json_symbols_are_single_bytes() {
+    let err = from_json(
+        r#"{"rules": [{"from": 0, "read": "ab", "write": "a", "dir": "R", "to": 0}], "accept": [], "max_steps": 1}"#,
+    );
+    assert!(err.is_err());
+}
+
+#[test]
+fn patterns_round_trip() {
+    let text = "tr\n0 * * R 0\n0 [ab] x R 0\n0 _ _ S 1\nacc\n1\nmax\n20\nrun\nabc\n";
+    let desc = parse(text.as_bytes()).unwrap();
+    let json = to_json(&desc);
+    assert!(json.contains("\"[ab]\""));
+    let back = from_json(&json).unwrap();
+    assert_eq!(to_json(&back), json);
+    let back = from_toml(&to_toml(&desc)).unwrap();
+    assert_eq!(to_json(&back), json);
+    assert_eq!(back.rules.transitions(0, b'c')[0].symb, b'c');
+    assert_eq!(back.rules.transitions(0, b'b')[0].symb, b'x');
+}
+
+#[test]
+fn multi_tape_round_trip() {
+    let text = "tapes\n2\ntr\n0 a _ a a R R 0\n0 _ _ _ _ S S 1\nacc\n1\nmax\n20\nrun\naa\n";
+    let desc = parse(text.as_bytes()).unwrap();
+    let json = to_json(&desc);
+    let back = from_json(&json).unwrap();
+    assert_eq!(back.rules.tapes(), 2);
+    assert_eq!(to_json(&back), json);
+    let back = from_toml(&to_toml(&desc)).unwrap();
+    assert_eq!(to_json(&back), json);
+    assert_eq!(verdicts(&back), vec![Verdict::Accept]);
+
+    let bad = json.replace("\"__\"", "\"_\"");
+    assert!(from_json(&bad).is_err());
+}